@@ -1,7 +1,7 @@
 use std::f32::consts::PI;
 
 use bevy::{
-    color::palettes::css::WHITE,
+    color::palettes::css::{RED, WHITE},
     pbr::wireframe::{WireframeConfig, WireframePlugin},
     prelude::*,
     render::{
@@ -12,7 +12,9 @@ use bevy::{
 use bevy_egui::EguiPlugin;
 use strategy_game::camera::OrbitCameraPlugin;
 use strategy_game::terrain_gen;
-use strategy_game::terrain_gen::{debug_ui_system, setup_image, SpawnTerrainMeshEvent};
+use strategy_game::terrain_gen::{
+    debug_ui_system, setup_image, ChunkLoader, TerrainCursor, TerrainMaterials, TerrainPlugin,
+};
 
 fn main() {
     App::new()
@@ -28,30 +30,45 @@ fn main() {
             WireframePlugin,
             EguiPlugin,
         ))
-        .add_event::<SpawnTerrainMeshEvent>()
+        .add_plugins(TerrainPlugin)
+        .init_resource::<TerrainMaterials>()
         .insert_resource(WireframeConfig {
             global: false,
             default_color: WHITE.into(),
         })
         .add_plugins(OrbitCameraPlugin)
-        .add_systems(
-            Startup,
-            (spawn_light, setup_image, terrain_gen::spawn_terrain_map),
-        )
+        .add_systems(Startup, (spawn_light, setup_image, spawn_chunk_loader))
         .add_systems(
             Update,
             (
                 terrain_gen::spawn_terrain_plain,
+                terrain_gen::update_terrain_lod,
                 terrain_gen::update_height_map_image,
             ),
         )
         .add_systems(
             Update,
-            (debug_ui_system, terrain_gen::debug_show_terrain_normals),
+            (
+                debug_ui_system,
+                terrain_gen::debug_show_terrain_normals,
+                gizmo_terrain_cursor,
+            ),
         )
         .run();
 }
 
+fn spawn_chunk_loader(mut cmd: Commands) {
+    // the camera orbits around the origin, so load the chunks there
+    cmd.spawn((ChunkLoader::new(2), TransformBundle::default()));
+}
+
+fn gizmo_terrain_cursor(mut gizmos: Gizmos, cursor: Res<TerrainCursor>) {
+    let Some(hit) = cursor.hit else {
+        return;
+    };
+    gizmos.arrow(hit.position, hit.position + hit.normal * 0.5, RED);
+}
+
 fn spawn_light(mut cmd: Commands) {
     // cmd.spawn(PointLightBundle {
     //     transform: Transform::from_xyz(0.0, 4.0, 0.0),
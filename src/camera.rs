@@ -1,6 +1,7 @@
 use bevy::prelude::*;
 
 pub struct OrbitCameraPlugin;
+#[derive(Default)]
 pub struct CameraConfig {}
 
 #[derive(Component)]
@@ -18,12 +19,6 @@ impl Plugin for OrbitCameraPlugin {
     }
 }
 
-impl Default for CameraConfig {
-    fn default() -> Self {
-        Self {}
-    }
-}
-
 pub fn spawn_camera(mut cmd: Commands) {
     cmd.spawn((
         Camera3dBundle {
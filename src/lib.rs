@@ -1,3 +1,3 @@
+pub mod camera;
 pub mod terrain_gen;
 pub mod util;
-pub mod camera;
@@ -0,0 +1,581 @@
+//! Biomes from independent temperature and moisture fields, looked up in a
+//! Whittaker style table.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{pixels::Pixel, ChunkId, HeightMap, RiverNetwork, Surface, TerrainConfig, TerrainMap};
+use crate::util::{
+    noise::{FractalSettings, NoiseSource, PerlinNoise},
+    rng::SplitMix64,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    /// everything below the sea level
+    Ocean,
+    Beach,
+    Desert,
+    Savanna,
+    Grassland,
+    Forest,
+    RainForest,
+    Swamp,
+    Taiga,
+    Tundra,
+    Snow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+    Wood,
+    Stone,
+    Ore,
+    Game,
+    Fish,
+}
+
+impl Biome {
+    /// sRGB color of the terrain
+    pub fn color(self) -> Pixel {
+        match self {
+            Biome::Ocean => [0, 50, 200, 255],
+            Biome::Beach => [220, 210, 150, 255],
+            Biome::Desert => [230, 200, 120, 255],
+            Biome::Savanna => [180, 180, 80, 255],
+            Biome::Grassland => [120, 190, 70, 255],
+            Biome::Forest => [40, 130, 40, 255],
+            Biome::RainForest => [20, 100, 40, 255],
+            Biome::Swamp => [70, 100, 60, 255],
+            Biome::Taiga => [60, 110, 90, 255],
+            Biome::Tundra => [150, 160, 130, 255],
+            Biome::Snow => [240, 240, 250, 255],
+        }
+    }
+
+    /// cost of moving a land unit over one world unit, infinite if impassable
+    pub fn movement_cost(self) -> f32 {
+        match self {
+            Biome::Ocean => f32::INFINITY,
+            Biome::Savanna | Biome::Grassland => 1.0,
+            Biome::Beach => 1.2,
+            Biome::Desert | Biome::Forest | Biome::Tundra => 1.5,
+            Biome::Taiga => 1.7,
+            Biome::RainForest => 2.0,
+            Biome::Snow => 2.5,
+            Biome::Swamp => 3.0,
+        }
+    }
+
+    /// the resources found in the biome and the chance of a deposit per sample
+    pub fn resources(self) -> &'static [(ResourceKind, f32)] {
+        match self {
+            Biome::Ocean => &[(ResourceKind::Fish, 0.002)],
+            Biome::Beach => &[(ResourceKind::Fish, 0.004)],
+            Biome::Desert => &[(ResourceKind::Stone, 0.004), (ResourceKind::Ore, 0.002)],
+            Biome::Savanna => &[(ResourceKind::Game, 0.004)],
+            Biome::Grassland => &[(ResourceKind::Game, 0.003), (ResourceKind::Stone, 0.001)],
+            Biome::Forest => &[(ResourceKind::Wood, 0.01), (ResourceKind::Game, 0.002)],
+            Biome::RainForest => &[(ResourceKind::Wood, 0.015)],
+            Biome::Swamp => &[(ResourceKind::Wood, 0.003)],
+            Biome::Taiga => &[(ResourceKind::Wood, 0.008), (ResourceKind::Game, 0.002)],
+            Biome::Tundra => &[(ResourceKind::Stone, 0.003), (ResourceKind::Ore, 0.003)],
+            Biome::Snow => &[(ResourceKind::Ore, 0.002)],
+        }
+    }
+}
+
+/// Moisture columns from dry to wet, temperature rows from cold to hot.
+const WHITTAKER: [[Biome; 4]; 4] = [
+    [Biome::Tundra, Biome::Tundra, Biome::Snow, Biome::Snow],
+    [Biome::Tundra, Biome::Taiga, Biome::Taiga, Biome::Taiga],
+    [
+        Biome::Grassland,
+        Biome::Grassland,
+        Biome::Forest,
+        Biome::Swamp,
+    ],
+    [
+        Biome::Desert,
+        Biome::Savanna,
+        Biome::RainForest,
+        Biome::RainForest,
+    ],
+];
+/// upper temperature of the first three rows in °C
+const TEMPERATURE_BANDS: [f32; 3] = [-5.0, 5.0, 18.0];
+/// upper moisture of the first three columns
+const MOISTURE_BANDS: [f32; 3] = [0.25, 0.5, 0.75];
+
+/// the biome of a land sample
+pub fn whittaker(temperature: f32, moisture: f32) -> Biome {
+    let row = TEMPERATURE_BANDS.partition_point(|band| temperature >= *band);
+    let column = MOISTURE_BANDS.partition_point(|band| moisture >= *band);
+    WHITTAKER[row][column]
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BiomeSettings {
+    /// temperature at z = 0 in °C
+    pub equator_temperature: f32,
+    /// temperature at `latitude_extent` and beyond
+    pub pole_temperature: f32,
+    /// world units from the equator to the poles
+    pub latitude_extent: f32,
+    /// °C lost per world unit above the sea level
+    pub lapse_rate: f32,
+    /// °C the noise adds or removes at most
+    pub temperature_variation: f32,
+    pub temperature_noise: FractalSettings,
+    pub moisture_noise: FractalSettings,
+    /// world units over which water makes the land moister
+    pub water_distance: f32,
+    /// how much of the moisture comes from nearby water instead of the noise
+    pub water_influence: f32,
+}
+
+impl Default for BiomeSettings {
+    fn default() -> Self {
+        let noise = FractalSettings {
+            octaves: 4,
+            frequency: 0.01,
+            ..default()
+        };
+        Self {
+            equator_temperature: 30.0,
+            pole_temperature: -20.0,
+            latitude_extent: 200.0,
+            lapse_rate: 15.0,
+            temperature_variation: 5.0,
+            temperature_noise: noise.clone(),
+            moisture_noise: noise,
+            water_distance: 3.0,
+            water_influence: 0.4,
+        }
+    }
+}
+
+/// Temperature, moisture and biome of every sample of a chunk, indexed like the
+/// [`HeightMap`]. Computed when the chunk is generated.
+pub struct BiomeMap {
+    size: usize,
+    temperature: Vec<f32>,
+    moisture: Vec<f32>,
+    biomes: Vec<Biome>,
+}
+
+/// the water distance is computed on every `WATER_STEP`th sample
+const WATER_STEP: usize = 4;
+
+impl BiomeMap {
+    /// `noise` is the height noise of `map`, it is sampled around the chunk to find
+    /// water in the neighbouring chunks. `rivers` are the river and lake samples
+    /// around the chunk relative to its first sample, see [`TerrainMap::water_samples`].
+    pub fn new<N: NoiseSource + ?Sized>(
+        id: ChunkId,
+        map: &HeightMap,
+        noise: &N,
+        config: &TerrainConfig,
+        seed: u64,
+        rivers: &[(isize, isize)],
+    ) -> Self {
+        let cells = config.samples as isize - 1;
+        let water = WaterDistance::new(
+            (id.0 * cells, id.1 * cells),
+            config,
+            rivers,
+            |start, size| {
+                let coarse = TerrainConfig {
+                    samples: size,
+                    ..config.clone()
+                };
+                let origin = Vec2::new(start.0 as f32, start.1 as f32);
+                HeightMap::patch(origin, WATER_STEP as f32, noise, &coarse)
+                    .height_data
+                    .iter()
+                    .map(|height| height * config.height_scale < config.sea_level)
+                    .collect()
+            },
+        );
+        Self::build(id, map, &water, config, seed)
+    }
+
+    fn build(
+        id: ChunkId,
+        map: &HeightMap,
+        water: &WaterDistance,
+        config: &TerrainConfig,
+        seed: u64,
+    ) -> Self {
+        let settings = &config.biomes;
+        let size = map.size();
+        let cells = (config.samples - 1) as f32;
+        let spacing = config.sample_spacing();
+        let origin = Vec2::new(id.0 as f32, id.1 as f32) * cells;
+        let temperature_noise = PerlinNoise::with_seed(seed.wrapping_add(3));
+        let moisture_noise = PerlinNoise::with_seed(seed.wrapping_add(4));
+
+        let mut temperature = vec![0.0; size * size];
+        let mut moisture = vec![0.0; size * size];
+        let mut biomes = vec![Biome::Ocean; size * size];
+        for x in 0..size {
+            for y in 0..size {
+                let index = map.get_index(x, y);
+                let sample = origin + Vec2::new(x as f32, y as f32);
+                // chunk (0, 0) is centered on the origin
+                let world_z = sample.y * spacing - config.chunk_size / 2.0;
+                let height = map.get(x, y) * config.height_scale;
+
+                let latitude = (world_z.abs() / settings.latitude_extent).min(1.0);
+                let noise = temperature_noise.fractal_brownian_motion(
+                    sample.x,
+                    sample.y,
+                    &settings.temperature_noise,
+                );
+                temperature[index] = settings
+                    .equator_temperature
+                    .lerp(settings.pole_temperature, latitude)
+                    - settings.lapse_rate * (height - config.sea_level).max(0.0)
+                    + settings.temperature_variation * noise;
+
+                let noise = moisture_noise.fractal_brownian_motion(
+                    sample.x,
+                    sample.y,
+                    &settings.moisture_noise,
+                );
+                let nearby_water = 1.0 - (water.at(x, y) / settings.water_distance).clamp(0.0, 1.0);
+                moisture[index] = ((noise + 1.0) / 2.0)
+                    .lerp(nearby_water, settings.water_influence)
+                    .clamp(0.0, 1.0);
+
+                biomes[index] = match config.surface(height) {
+                    Surface::DeepWater | Surface::ShallowWater => Biome::Ocean,
+                    Surface::Shore => Biome::Beach,
+                    Surface::Land => whittaker(temperature[index], moisture[index]),
+                };
+            }
+        }
+        Self {
+            size,
+            temperature,
+            moisture,
+            biomes,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn get_index(&self, x: usize, y: usize) -> usize {
+        y + x * self.size
+    }
+
+    pub fn biome(&self, x: usize, y: usize) -> Biome {
+        self.biomes[self.get_index(x, y)]
+    }
+
+    /// in °C
+    pub fn temperature(&self, x: usize, y: usize) -> f32 {
+        self.temperature[self.get_index(x, y)]
+    }
+
+    /// in [0.0, 1.0]
+    pub fn moisture(&self, x: usize, y: usize) -> f32 {
+        self.moisture[self.get_index(x, y)]
+    }
+}
+
+/// samples the water distance grid reaches past the chunk border
+fn water_margin(config: &TerrainConfig) -> isize {
+    let step = config.sample_spacing() * WATER_STEP as f32;
+    (config.biomes.water_distance / step).ceil() as isize * WATER_STEP as isize
+}
+
+/// World distance to the nearest water, on a coarse grid that reaches `water_distance`
+/// past the chunk border. The grid is aligned to the global samples, so neighbouring
+/// chunks agree on their shared border.
+///
+/// Water is every sample below the sea level and every river and lake sample. Chunks
+/// that are generated find the water of their neighbours in the height noise, so they
+/// don't see post-processing of the neighbours until
+/// [`TerrainMap::rebuild_biomes`] reads the stored heights.
+struct WaterDistance {
+    size: usize,
+    /// samples from the first coarse sample to the chunk origin
+    offset: (usize, usize),
+    distance: Vec<f32>,
+}
+
+impl WaterDistance {
+    /// `origin` is the global index of the first sample of the chunk, `rivers` are
+    /// relative to it. `wet` tells for the coarse grid starting at a global sample
+    /// with the given size which samples are below the sea level, indexed like a
+    /// [`HeightMap`].
+    fn new(
+        origin: (isize, isize),
+        config: &TerrainConfig,
+        rivers: &[(isize, isize)],
+        wet: impl FnOnce((isize, isize), usize) -> Vec<bool>,
+    ) -> Self {
+        let step = config.sample_spacing() * WATER_STEP as f32;
+        let margin = water_margin(config);
+        let start =
+            |origin: isize| (origin - margin).div_euclid(WATER_STEP as isize) * WATER_STEP as isize;
+        let start = (start(origin.0), start(origin.1));
+        let offset = ((origin.0 - start.0) as usize, (origin.1 - start.1) as usize);
+        let size = (offset.0.max(offset.1) + config.samples - 1 + margin as usize)
+            .div_ceil(WATER_STEP)
+            + 1;
+        let mut distance: Vec<f32> = wet(start, size)
+            .into_iter()
+            .map(|wet| if wet { 0.0 } else { f32::INFINITY })
+            .collect();
+        // rivers are narrower than the grid, they wet the nearest coarse sample
+        for (x, z) in rivers {
+            let coarse = |i: isize, offset: usize| {
+                ((i + offset as isize) as f32 / WATER_STEP as f32).round() as isize
+            };
+            let (x, z) = (coarse(*x, offset.0), coarse(*z, offset.1));
+            if (0..size as isize).contains(&x) && (0..size as isize).contains(&z) {
+                distance[z as usize + x as usize * size] = 0.0;
+            }
+        }
+
+        // two pass chamfer distance transform
+        let diagonal = std::f32::consts::SQRT_2;
+        let forward = [
+            (-1, -1, diagonal),
+            (-1, 0, 1.0),
+            (-1, 1, diagonal),
+            (0, -1, 1.0),
+        ];
+        let mut relax = |x: usize, y: usize, sign: isize| {
+            for (dx, dy, cost) in forward {
+                let (nx, ny) = (x as isize + dx * sign, y as isize + dy * sign);
+                if nx < 0 || ny < 0 || nx >= size as isize || ny >= size as isize {
+                    continue;
+                }
+                let neighbour = distance[ny as usize + nx as usize * size] + cost;
+                let index = y + x * size;
+                distance[index] = distance[index].min(neighbour);
+            }
+        };
+        for x in 0..size {
+            for y in 0..size {
+                relax(x, y, 1);
+            }
+        }
+        for x in (0..size).rev() {
+            for y in (0..size).rev() {
+                relax(x, y, -1);
+            }
+        }
+        distance.iter_mut().for_each(|d| *d *= step);
+        Self {
+            size,
+            offset,
+            distance,
+        }
+    }
+
+    /// bilinear between the coarse samples around the chunk sample
+    fn at(&self, x: usize, y: usize) -> f32 {
+        let coarse = |i: usize| i as f32 / WATER_STEP as f32;
+        let (cx, cy) = (coarse(x + self.offset.0), coarse(y + self.offset.1));
+        let (x0, y0) = (cx.floor() as usize, cy.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.size - 1), (y0 + 1).min(self.size - 1));
+        let (tx, ty) = (cx.fract(), cy.fract());
+        let get = |x: usize, y: usize| self.distance[y + x * self.size];
+        let near = get(x0, y0).lerp(get(x1, y0), tx);
+        let far = get(x0, y1).lerp(get(x1, y1), tx);
+        near.lerp(far, ty)
+    }
+}
+
+/// A resource placed by [`TerrainMap::place_resources`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResourceDeposit {
+    pub kind: ResourceKind,
+    /// world position on the terrain
+    pub position: Vec3,
+}
+
+impl TerrainMap {
+    /// Builds the biome maps of the chunks again from their stored heights, after
+    /// post-processing or new rivers. Samples of chunks that aren't generated count
+    /// as land.
+    pub fn rebuild_biomes(&mut self, ids: &[ChunkId], rivers: &RiverNetwork) {
+        let cells = self.config.samples as isize - 1;
+        for &id in ids {
+            let Some(chunk) = self.chunks.get(&id) else {
+                continue;
+            };
+            let rivers = self.water_samples(id, rivers);
+            let water = WaterDistance::new(
+                (id.0 * cells, id.1 * cells),
+                &self.config,
+                &rivers,
+                |start, size| {
+                    let mut wet = Vec::with_capacity(size * size);
+                    for x in 0..size as isize {
+                        for z in 0..size as isize {
+                            let height = self.height_sample(
+                                start.0 + x * WATER_STEP as isize,
+                                start.1 + z * WATER_STEP as isize,
+                            );
+                            wet.push(height.is_some_and(|height| {
+                                height * self.config.height_scale < self.config.sea_level
+                            }));
+                        }
+                    }
+                    wet
+                },
+            );
+            let biome_map = BiomeMap::build(
+                self.canonical_id(id),
+                &chunk.height_map,
+                &water,
+                &self.config,
+                self.config.seed,
+            );
+            if let Some(chunk) = self.chunks.get_mut(&id) {
+                chunk.biome_map = biome_map;
+            }
+        }
+    }
+
+    /// the river and lake samples close enough to moisten the chunk, relative to its
+    /// first sample
+    pub fn water_samples(&self, id: ChunkId, rivers: &RiverNetwork) -> Vec<(isize, isize)> {
+        let cells = self.config.samples as isize - 1;
+        let reach = -water_margin(&self.config) - WATER_STEP as isize
+            ..=cells + water_margin(&self.config) + WATER_STEP as isize;
+        rivers
+            .samples()
+            .map(|(x, z)| (x - id.0 * cells, z - id.1 * cells))
+            .filter(|(x, z)| reach.contains(x) && reach.contains(z))
+            .collect()
+    }
+
+    /// the biome of the sample nearest to the world (x, z) position, `None` if the
+    /// chunk isn't generated
+    pub fn biome_at(&self, position: Vec2) -> Option<Biome> {
+        let sample = (position + self.config.chunk_size / 2.0) / self.config.sample_spacing();
+        let (chunk, x, z) = self.sample(sample.x.round() as isize, sample.y.round() as isize)?;
+        Some(chunk.biome_map.biome(x, z))
+    }
+
+    /// cost of moving a land unit over one world unit at the position, see
+    /// [`Biome::movement_cost`]
+    pub fn movement_cost(&self, position: Vec2) -> Option<f32> {
+        Some(self.biome_at(position)?.movement_cost())
+    }
+
+    /// Places the resources of a generated chunk by the chances of its biomes. The
+    /// same seed and chunk always give the same deposits.
+    pub fn place_resources(&self, id: ChunkId) -> Vec<ResourceDeposit> {
+        let Some(chunk) = self.chunks.get(&id) else {
+            return vec![];
+        };
+        let mut rng = SplitMix64::new(
+            self.config.seed
+                ^ (id.0 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                ^ (id.1 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F),
+        );
+        let spacing = self.config.sample_spacing();
+        let center = Vec2::new(id.0 as f32, id.1 as f32) * self.config.chunk_size;
+        let map = &chunk.height_map;
+        let mut deposits = vec![];
+        // the last row and column belong to the next chunk as well, skip them so
+        // their deposits aren't placed twice
+        for x in 0..map.size() - 1 {
+            for z in 0..map.size() - 1 {
+                for (kind, chance) in chunk.biome_map.biome(x, z).resources() {
+                    if rng.next_f32() < *chance {
+                        deposits.push(ResourceDeposit {
+                            kind: *kind,
+                            position: Vec3::new(
+                                center.x + x as f32 * spacing - self.config.chunk_size / 2.0,
+                                map.get(x, z) * self.config.height_scale,
+                                center.y + z as f32 * spacing - self.config.chunk_size / 2.0,
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+        deposits
+    }
+}
+
+/// Rebuilds the biomes of the chunks in [`TerrainMap::biomes_changed`] with the rivers
+/// and lakes of the [`RiverNetwork`], before they are meshed again.
+pub fn rebuild_changed_biomes(mut map: ResMut<TerrainMap>, rivers: Res<RiverNetwork>) {
+    if map.biomes_changed.is_empty() {
+        return;
+    }
+    let ids: Vec<ChunkId> = map.biomes_changed.drain().collect();
+    map.rebuild_biomes(&ids, &rivers);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_gen::test_terrain;
+
+    #[test]
+    fn whittaker_corners() {
+        assert_eq!(whittaker(-20.0, 0.0), Biome::Tundra);
+        assert_eq!(whittaker(-20.0, 1.0), Biome::Snow);
+        assert_eq!(whittaker(30.0, 0.0), Biome::Desert);
+        assert_eq!(whittaker(30.0, 1.0), Biome::RainForest);
+        assert_eq!(whittaker(10.0, 0.6), Biome::Forest);
+        assert_eq!(whittaker(10.0, 0.9), Biome::Swamp);
+    }
+
+    #[test]
+    fn whittaker_band_limits_belong_to_the_upper_band() {
+        assert_eq!(whittaker(-5.0 - 1e-3, 0.3), Biome::Tundra);
+        assert_eq!(whittaker(-5.0, 0.3), Biome::Taiga);
+        assert_eq!(whittaker(18.0 - 1e-3, 0.1), Biome::Grassland);
+        assert_eq!(whittaker(18.0, 0.1), Biome::Desert);
+        assert_eq!(whittaker(25.0, 0.25 - 1e-3), Biome::Desert);
+        assert_eq!(whittaker(25.0, 0.25), Biome::Savanna);
+        assert_eq!(whittaker(25.0, 0.5), Biome::RainForest);
+    }
+
+    #[test]
+    fn rebuilding_unchanged_heights_keeps_the_biomes() {
+        let mut map = test_terrain();
+        let size = map.config.samples;
+        let generated: Vec<_> = (0..size * size)
+            .map(|i| {
+                let biomes = &map.chunks[&(0, 0)].biome_map;
+                let (x, z) = (i / size, i % size);
+                (biomes.biome(x, z), biomes.moisture(x, z))
+            })
+            .collect();
+        map.rebuild_biomes(&[(0, 0)], &RiverNetwork::default());
+        let biomes = &map.chunks[&(0, 0)].biome_map;
+        for (i, expected) in generated.into_iter().enumerate() {
+            let (x, z) = (i / size, i % size);
+            assert_eq!((biomes.biome(x, z), biomes.moisture(x, z)), expected);
+        }
+    }
+
+    #[test]
+    fn rivers_moisten_the_land() {
+        let mut map = test_terrain();
+        let before = map.chunks[&(0, 0)].biome_map.moisture(8, 8);
+        let rivers = RiverNetwork::through(&[(8, 8)]);
+        map.rebuild_biomes(&[(0, 0)], &rivers);
+        let biomes = &map.chunks[&(0, 0)].biome_map;
+        let settings = &map.config.biomes;
+        assert!(biomes.moisture(8, 8) >= before);
+        assert!(biomes.moisture(8, 8) >= settings.water_influence);
+        // the chunk next to it sees the river only once it is rebuilt as well
+        assert_eq!(map.water_samples((1, 0), &rivers), [(-8, 8)]);
+    }
+}
@@ -0,0 +1,389 @@
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    region::{HeightField, NEIGHBOURS},
+    ChunkId, TerrainMap,
+};
+use crate::util::rng::SplitMix64;
+
+/// Droplet based hydraulic erosion, after Hans Theobald Beyer's "Implementation of a
+/// method for hydraulic erosion". Every droplet runs downhill, picks up sediment
+/// where it speeds up and drops it where it slows down or evaporates.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HydraulicErosion {
+    pub droplets: usize,
+    /// steps before a droplet is dropped
+    pub max_lifetime: usize,
+    /// how much a droplet keeps its direction instead of following the slope, [0.0, 1.0]
+    pub inertia: f32,
+    /// sediment a droplet can carry per unit of speed, water and slope
+    pub capacity: f32,
+    pub min_capacity: f32,
+    /// fraction of the excess sediment dropped per step
+    pub deposition: f32,
+    /// fraction of the free capacity eroded per step
+    pub erosion: f32,
+    /// fraction of the water evaporated per step
+    pub evaporation: f32,
+    pub gravity: f32,
+    /// samples around the droplet the erosion is spread over
+    pub radius: usize,
+    pub initial_water: f32,
+    pub initial_speed: f32,
+}
+
+impl Default for HydraulicErosion {
+    fn default() -> Self {
+        Self {
+            droplets: 30_000,
+            max_lifetime: 30,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_capacity: 0.01,
+            deposition: 0.3,
+            erosion: 0.3,
+            evaporation: 0.01,
+            gravity: 4.0,
+            radius: 3,
+            initial_water: 1.0,
+            initial_speed: 1.0,
+        }
+    }
+}
+
+impl HydraulicErosion {
+    /// erodes the height field, the same seed always gives the same result
+    pub fn erode(&self, field: &mut HeightField, seed: u64) {
+        if field.width < 2 || field.height < 2 {
+            return;
+        }
+        let mut rng = SplitMix64::new(seed);
+        let brush = self.brush();
+        let max = Vec2::new((field.width - 1) as f32, (field.height - 1) as f32);
+        for _ in 0..self.droplets {
+            let mut position = Vec2::new(rng.next_f32(), rng.next_f32()) * max;
+            let mut direction = Vec2::ZERO;
+            let mut speed = self.initial_speed;
+            let mut water = self.initial_water;
+            let mut sediment = 0.0;
+            for _ in 0..self.max_lifetime {
+                let cell = position.floor();
+                let (height, gradient) = height_and_gradient(field, position);
+                direction = direction * self.inertia - gradient * (1.0 - self.inertia);
+                if direction.length_squared() == 0.0 {
+                    break;
+                }
+                direction = direction.normalize();
+                let previous = position;
+                position += direction;
+                if position.x < 0.0
+                    || position.y < 0.0
+                    || position.x >= max.x
+                    || position.y >= max.y
+                {
+                    break;
+                }
+                let delta = height_and_gradient(field, position).0 - height;
+                let capacity = (-delta * speed * water * self.capacity).max(self.min_capacity);
+                if sediment > capacity || delta > 0.0 {
+                    // fill the pit it just left or drop what it can't carry
+                    let amount = if delta > 0.0 {
+                        delta.min(sediment)
+                    } else {
+                        (sediment - capacity) * self.deposition
+                    };
+                    sediment -= amount;
+                    deposit(field, previous, cell, amount);
+                } else {
+                    let amount = ((capacity - sediment) * self.erosion).min(-delta);
+                    for &(dx, dz, weight) in brush.iter() {
+                        let x = cell.x as isize + dx;
+                        let z = cell.y as isize + dz;
+                        if x < 0 || z < 0 || x >= field.width as isize || z >= field.height as isize
+                        {
+                            continue;
+                        }
+                        let (x, z) = (x as usize, z as usize);
+                        let removed = amount * weight;
+                        field.set(x, z, field.get(x, z) - removed);
+                        sediment += removed;
+                    }
+                }
+                speed = (speed * speed - delta * self.gravity).max(0.0).sqrt();
+                water *= 1.0 - self.evaporation;
+            }
+        }
+    }
+
+    /// offsets around a sample and their weights, which sum up to 1
+    fn brush(&self) -> Vec<(isize, isize, f32)> {
+        let radius = self.radius.max(1) as isize;
+        let mut brush = Vec::new();
+        for dz in -radius + 1..=radius {
+            for dx in -radius + 1..=radius {
+                let distance = ((dx * dx + dz * dz) as f32).sqrt();
+                let weight = radius as f32 - distance;
+                if weight > 0.0 {
+                    brush.push((dx, dz, weight));
+                }
+            }
+        }
+        let sum: f32 = brush.iter().map(|(_, _, weight)| weight).sum();
+        for (_, _, weight) in brush.iter_mut() {
+            *weight /= sum;
+        }
+        brush
+    }
+}
+
+/// bilinear height and its gradient at a position inside the field
+fn height_and_gradient(field: &HeightField, position: Vec2) -> (f32, Vec2) {
+    let x = (position.x as usize).min(field.width - 2);
+    let z = (position.y as usize).min(field.height - 2);
+    let u = position.x - x as f32;
+    let v = position.y - z as f32;
+    let h00 = field.get(x, z);
+    let h10 = field.get(x + 1, z);
+    let h01 = field.get(x, z + 1);
+    let h11 = field.get(x + 1, z + 1);
+    let gradient = Vec2::new(
+        (h10 - h00) * (1.0 - v) + (h11 - h01) * v,
+        (h01 - h00) * (1.0 - u) + (h11 - h10) * u,
+    );
+    let height =
+        h00 * (1.0 - u) * (1.0 - v) + h10 * u * (1.0 - v) + h01 * (1.0 - u) * v + h11 * u * v;
+    (height, gradient)
+}
+
+/// spreads the sediment bilinearly over the corners of the cell
+fn deposit(field: &mut HeightField, position: Vec2, cell: Vec2, amount: f32) {
+    let (x, z) = (cell.x as usize, cell.y as usize);
+    let u = position.x - cell.x;
+    let v = position.y - cell.y;
+    for (dx, dz, weight) in [
+        (0, 0, (1.0 - u) * (1.0 - v)),
+        (1, 0, u * (1.0 - v)),
+        (0, 1, (1.0 - u) * v),
+        (1, 1, u * v),
+    ] {
+        let (x, z) = (x + dx, z + dz);
+        field.set(x, z, field.get(x, z) + amount * weight);
+    }
+}
+
+/// Thermal erosion, material on slopes steeper than the talus angle slides down
+/// to the lower neighbours until the slopes relax to that angle.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThermalErosion {
+    /// steepest stable slope in degrees
+    pub talus_angle: f32,
+    pub iterations: usize,
+    /// fraction of the excess material moved per iteration, (0.0, 1.0]
+    pub transfer_rate: f32,
+}
+
+impl Default for ThermalErosion {
+    fn default() -> Self {
+        Self {
+            talus_angle: 35.0,
+            iterations: 50,
+            transfer_rate: 0.5,
+        }
+    }
+}
+
+impl ThermalErosion {
+    pub fn erode(&self, field: &mut HeightField) {
+        // the largest stable height difference to a direct neighbour, in height units
+        let talus = self.talus_angle.to_radians().tan() * field.sample_spacing / field.height_scale;
+        let mut change = vec![0.0; field.data.len()];
+        for _ in 0..self.iterations {
+            change.fill(0.0);
+            for index in 0..field.data.len() {
+                let center = field.data[index];
+                let mut excess = [(0, 0.0); NEIGHBOURS.len()];
+                let mut count = 0;
+                let mut total = 0.0;
+                let mut steepest: f32 = 0.0;
+                for (neighbour, distance) in field.neighbours(index) {
+                    let difference = center - field.data[neighbour];
+                    let over = difference - talus * distance;
+                    if over > 0.0 {
+                        excess[count] = (neighbour, over);
+                        count += 1;
+                        total += over;
+                        steepest = steepest.max(over);
+                    }
+                }
+                if total == 0.0 {
+                    continue;
+                }
+                // moving half the excess would level the steepest pair
+                let moved = self.transfer_rate * steepest / 2.0;
+                change[index] -= moved;
+                for (neighbour, over) in excess[..count].iter() {
+                    change[*neighbour] += moved * over / total;
+                }
+            }
+            for (height, change) in field.data.iter_mut().zip(change.iter()) {
+                *height += change;
+            }
+        }
+    }
+}
+
+/// A post-processing step of the terrain heights.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PostProcess {
+    Hydraulic(HydraulicErosion),
+    Thermal(ThermalErosion),
+}
+
+impl PostProcess {
+    pub fn apply(&self, field: &mut HeightField, seed: u64) {
+        match self {
+            PostProcess::Hydraulic(erosion) => erosion.erode(field, seed),
+            PostProcess::Thermal(erosion) => erosion.erode(field),
+        }
+    }
+}
+
+impl TerrainMap {
+    /// Erodes the chunks from `min` to `max` inclusive, see [`TerrainMap::process_region`].
+    /// Chunks that are evicted and generated again lose their erosion.
+    pub fn erode_hydraulic(
+        &mut self,
+        min: ChunkId,
+        max: ChunkId,
+        erosion: &HydraulicErosion,
+        seed: u64,
+    ) -> Option<Vec<ChunkId>> {
+        self.process_region(min, max, |field| erosion.erode(field, seed))
+    }
+
+    /// Runs the steps in their order over the chunks from `min` to `max` inclusive,
+    /// see [`TerrainMap::process_region`]. Every step gets its own seed derived from `seed`.
+    pub fn post_process(
+        &mut self,
+        min: ChunkId,
+        max: ChunkId,
+        steps: &[PostProcess],
+        seed: u64,
+    ) -> Option<Vec<ChunkId>> {
+        self.process_region(min, max, |field| {
+            for (i, step) in steps.iter().enumerate() {
+                step.apply(field, seed.wrapping_add(i as u64));
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise::PerlinNoise;
+
+    const SIZE: usize = 65;
+
+    fn hills() -> HeightField {
+        let noise = PerlinNoise::with_seed(3);
+        let mut data = Vec::with_capacity(SIZE * SIZE);
+        for z in 0..SIZE {
+            for x in 0..SIZE {
+                data.push(noise.noise2d_with_freq(x as f32, z as f32, 0.05) * 0.5 + 0.5);
+            }
+        }
+        HeightField {
+            width: SIZE,
+            height: SIZE,
+            origin: (-32, -32),
+            data,
+            sample_spacing: 1.0,
+            height_scale: 20.0,
+        }
+    }
+
+    fn hydraulic() -> HydraulicErosion {
+        HydraulicErosion {
+            droplets: 5_000,
+            ..HydraulicErosion::default()
+        }
+    }
+
+    #[test]
+    fn hydraulic_erosion_is_deterministic() {
+        let (mut first, mut second, mut other) = (hills(), hills(), hills());
+        hydraulic().erode(&mut first, 7);
+        hydraulic().erode(&mut second, 7);
+        hydraulic().erode(&mut other, 8);
+        assert_eq!(first.data, second.data);
+        assert_ne!(first.data, other.data);
+        assert_ne!(first.data, hills().data);
+    }
+
+    #[test]
+    fn hydraulic_erosion_stays_finite_and_bounded() {
+        let original = hills();
+        let mut field = original.clone();
+        hydraulic().erode(&mut field, 7);
+        let (low, high) = original
+            .data
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(low, high), &h| {
+                (low.min(h), high.max(h))
+            });
+        for z in 0..SIZE {
+            for x in 0..SIZE {
+                let height = field.get(x, z);
+                assert!(height.is_finite(), "({x}, {z}) is {height}");
+                assert!(
+                    (low - 0.5..=high + 0.5).contains(&height),
+                    "({x}, {z}) is {height}"
+                );
+            }
+        }
+        // sediment is only moved around, what the droplets still carry at the end is lost
+        let before: f32 = original.data.iter().sum();
+        let after: f32 = field.data.iter().sum();
+        assert!(after <= before + 1e-2 && after > before * 0.8);
+    }
+
+    #[test]
+    fn thermal_erosion_keeps_the_mass_and_relaxes_slopes() {
+        let mut field = hills();
+        field.height_scale = 200.0;
+        let before: f32 = field.data.iter().sum();
+        let steepest = |field: &HeightField| {
+            let mut steepest: f32 = 0.0;
+            for z in 0..SIZE {
+                for x in 1..SIZE {
+                    steepest = steepest.max((field.get(x, z) - field.get(x - 1, z)).abs());
+                }
+            }
+            steepest
+        };
+        let initial = steepest(&field);
+        ThermalErosion::default().erode(&mut field);
+        let after: f32 = field.data.iter().sum();
+        assert!(field.data.iter().all(|height| height.is_finite()));
+        assert!((before - after).abs() < 1e-2);
+        assert!(steepest(&field) < initial);
+    }
+
+    #[test]
+    fn tiny_fields_are_left_alone() {
+        let mut field = HeightField {
+            width: 1,
+            height: 1,
+            origin: (0, 0),
+            data: vec![0.5],
+            sample_spacing: 1.0,
+            height_scale: 1.0,
+        };
+        hydraulic().erode(&mut field, 0);
+        assert_eq!(field.data, [0.5]);
+    }
+}
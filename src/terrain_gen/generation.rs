@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use bevy::{
+    log,
+    prelude::*,
+    tasks::{block_on, AsyncComputeTaskPool, Task},
+};
+
+use super::{BiomeMap, Chunk, ChunkId, HeightMap, RiverNetwork, SpawnTerrainMeshEvent, TerrainMap};
+use crate::util::noise::NoiseNode;
+
+/// A chunk whose height and biome maps are generated in the background.
+pub struct PendingChunk {
+    task: Task<Chunk>,
+}
+
+/// How many generated chunks are handed to meshing per frame, so finishing many
+/// tasks at once doesn't hitch a single frame.
+#[derive(Resource)]
+pub struct ChunkGenerationBudget {
+    pub chunks_per_frame: usize,
+}
+
+impl Default for ChunkGenerationBudget {
+    fn default() -> Self {
+        Self {
+            chunks_per_frame: 4,
+        }
+    }
+}
+
+impl TerrainMap {
+    /// starts generating the chunk on the [`AsyncComputeTaskPool`], returns `false`
+    /// if the chunk is already loaded or pending
+    pub fn request_chunk(
+        &mut self,
+        id: ChunkId,
+        noise: &Arc<NoiseNode>,
+        rivers: &RiverNetwork,
+    ) -> bool {
+        if self.chunks.contains_key(&id) || self.pending.contains_key(&id) {
+            return false;
+        }
+        let canonical_id = self.canonical_id(id);
+        let noise = noise.clone();
+        let config = self.config.clone();
+        let seed = self.config.seed;
+        let rivers = self.water_samples(id, rivers);
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            let height_map = HeightMap::new(canonical_id, noise.as_ref(), &config);
+            let biome_map = BiomeMap::new(
+                canonical_id,
+                &height_map,
+                noise.as_ref(),
+                &config,
+                seed,
+                &rivers,
+            );
+            Chunk {
+                height_map,
+                biome_map,
+            }
+        });
+        self.pending.insert(id, PendingChunk { task });
+        true
+    }
+}
+
+/// Moves finished chunks into the map, recomputes the normals at their seams and
+/// sends a [`SpawnTerrainMeshEvent`] for each, at most [`ChunkGenerationBudget::chunks_per_frame`] per frame.
+pub fn finish_pending_chunks(
+    mut map: ResMut<TerrainMap>,
+    budget: Res<ChunkGenerationBudget>,
+    mut event: EventWriter<SpawnTerrainMeshEvent>,
+) {
+    let finished: Vec<ChunkId> = map
+        .pending
+        .iter()
+        .filter(|(_, pending)| pending.task.is_finished())
+        .map(|(id, _)| *id)
+        .take(budget.chunks_per_frame)
+        .collect();
+    for id in finished {
+        let Some(pending) = map.pending.remove(&id) else {
+            continue;
+        };
+        let chunk = block_on(pending.task);
+        map.chunks.insert(id, chunk);
+        map.chunk_generated(id);
+        event.send(SpawnTerrainMeshEvent(id));
+        log::info!("Created chunk: {:?}", id);
+    }
+}
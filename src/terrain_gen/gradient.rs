@@ -0,0 +1,306 @@
+use std::fmt;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use super::pixels::Pixel;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GradientInterpolation {
+    #[default]
+    Linear,
+    /// the color of the last stop at or below the value, hard bands
+    Step,
+}
+
+/// The color space the stops are blended in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendSpace {
+    /// blends the sRGB bytes, darker in between saturated colors
+    #[default]
+    Srgb,
+    /// physically correct blending
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GradientStop {
+    pub position: f32,
+    /// sRGB
+    pub color: Pixel,
+}
+
+/// Maps values in [0.0, 1.0] to colors. Values outside of the stops get the color
+/// of the first or last stop.
+///
+/// Loads as an asset from `.gradient.ron` and `.gradient.json` files, e.g.
+/// `(stops: [(position: 0.0, color: (0, 0, 0, 255)), (position: 1.0, color: (255, 255, 255, 255))])`
+#[derive(Asset, TypePath, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "GradientDescription")]
+pub struct ColorGradient {
+    stops: Vec<GradientStop>,
+    pub interpolation: GradientInterpolation,
+    pub blend: BlendSpace,
+}
+
+/// a deserialized [`ColorGradient`] whose stops may be in any order
+#[derive(Deserialize)]
+struct GradientDescription {
+    stops: Vec<GradientStop>,
+    #[serde(default)]
+    interpolation: GradientInterpolation,
+    #[serde(default)]
+    blend: BlendSpace,
+}
+
+impl From<GradientDescription> for ColorGradient {
+    fn from(description: GradientDescription) -> Self {
+        Self {
+            stops: description.stops,
+            interpolation: description.interpolation,
+            blend: description.blend,
+        }
+        .sorted()
+    }
+}
+
+impl ColorGradient {
+    /// the stops are sorted by position
+    pub fn new(stops: impl IntoIterator<Item = (f32, Pixel)>) -> Self {
+        Self {
+            stops: stops
+                .into_iter()
+                .map(|(position, color)| GradientStop { position, color })
+                .collect(),
+            interpolation: GradientInterpolation::Linear,
+            blend: BlendSpace::Srgb,
+        }
+        .sorted()
+    }
+
+    pub fn from_ron(description: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(description)
+    }
+
+    pub fn from_json(description: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(description)
+    }
+
+    pub fn with_interpolation(mut self, interpolation: GradientInterpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendSpace) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn stops(&self) -> &[GradientStop] {
+        &self.stops
+    }
+
+    fn sorted(mut self) -> Self {
+        self.stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        self
+    }
+
+    /// height colors with water below 0.4
+    pub fn terrain() -> Self {
+        Self::new([
+            (0.1, [0, 0, 120, 255]),
+            (0.3, [0, 50, 200, 255]),
+            (0.4, [0, 200, 200, 255]),
+            (0.45, [150, 200, 50, 255]),
+            (0.5, [20, 200, 50, 255]),
+            (0.7, [100, 100, 100, 255]),
+            (0.9, [150, 200, 200, 255]),
+        ])
+    }
+
+    pub fn grayscale() -> Self {
+        Self::new([(0.0, [0, 0, 0, 255]), (1.0, [255, 255, 255, 255])])
+    }
+
+    pub fn heat() -> Self {
+        Self::new([
+            (0.0, [0, 0, 0, 255]),
+            (0.35, [200, 0, 0, 255]),
+            (0.7, [255, 200, 0, 255]),
+            (1.0, [255, 255, 255, 255]),
+        ])
+        .with_blend(BlendSpace::Linear)
+    }
+
+    /// for slopes from flat at 0.0 to vertical at 1.0
+    pub fn slope() -> Self {
+        Self::new([
+            (0.0, [60, 170, 60, 255]),
+            (0.2, [220, 210, 60, 255]),
+            (0.4, [150, 90, 40, 255]),
+            (0.6, [110, 110, 110, 255]),
+        ])
+    }
+
+    /// the color of the value, transparent black without stops
+    pub fn sample(&self, t: f32) -> Pixel {
+        let (Some(first), Some(last)) = (self.stops.first(), self.stops.last()) else {
+            return [0, 0, 0, 0];
+        };
+        // the first stop above t
+        let next = self.stops.partition_point(|stop| stop.position <= t);
+        if next == 0 {
+            return first.color;
+        }
+        if next == self.stops.len() {
+            return last.color;
+        }
+        let (start, end) = (self.stops[next - 1], self.stops[next]);
+        if self.interpolation == GradientInterpolation::Step {
+            return start.color;
+        }
+        let ratio = (t - start.position) / (end.position - start.position);
+        match self.blend {
+            BlendSpace::Srgb => lerp_pixel(start.color, end.color, ratio),
+            BlendSpace::Linear => {
+                let linear = |[r, g, b, a]: Pixel| Color::srgba_u8(r, g, b, a).to_linear();
+                let (start, end) = (linear(start.color), linear(end.color));
+                let blended = LinearRgba::new(
+                    start.red.lerp(end.red, ratio),
+                    start.green.lerp(end.green, ratio),
+                    start.blue.lerp(end.blue, ratio),
+                    start.alpha.lerp(end.alpha, ratio),
+                );
+                Srgba::from(blended).to_u8_array()
+            }
+        }
+    }
+}
+
+fn lerp_pixel(start: Pixel, end: Pixel, t: f32) -> Pixel {
+    let r = (start[0] as f32 + t * (end[0] as f32 - start[0] as f32)) as u8;
+    let g = (start[1] as f32 + t * (end[1] as f32 - start[1] as f32)) as u8;
+    let b = (start[2] as f32 + t * (end[2] as f32 - start[2] as f32)) as u8;
+    let a = (start[3] as f32 + t * (end[3] as f32 - start[3] as f32)) as u8;
+    [r, g, b, a]
+}
+
+/// Loads `.gradient.ron` and `.gradient.json` files as [`ColorGradient`].
+#[derive(Default)]
+pub struct ColorGradientLoader;
+
+#[derive(Debug)]
+pub enum GradientLoadError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for GradientLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GradientLoadError::Io(err) => write!(f, "could not read the gradient: {err}"),
+            GradientLoadError::Ron(err) => write!(f, "invalid gradient ron: {err}"),
+            GradientLoadError::Json(err) => write!(f, "invalid gradient json: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for GradientLoadError {}
+
+impl AssetLoader for ColorGradientLoader {
+    type Asset = ColorGradient;
+    type Settings = ();
+    type Error = GradientLoadError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<ColorGradient, GradientLoadError> {
+        let mut description = String::new();
+        reader
+            .read_to_string(&mut description)
+            .await
+            .map_err(GradientLoadError::Io)?;
+        let is_json = load_context
+            .path()
+            .extension()
+            .is_some_and(|extension| extension == "json");
+        if is_json {
+            ColorGradient::from_json(&description).map_err(GradientLoadError::Json)
+        } else {
+            ColorGradient::from_ron(&description).map_err(GradientLoadError::Ron)
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["gradient.ron", "gradient.json"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn black_to_white() -> ColorGradient {
+        ColorGradient::new([(0.2, [0, 0, 0, 255]), (0.6, [255, 255, 255, 255])])
+    }
+
+    #[test]
+    fn sample_clamps_outside_of_the_stops() {
+        let gradient = black_to_white();
+        assert_eq!(gradient.sample(-1.0), [0, 0, 0, 255]);
+        assert_eq!(gradient.sample(0.0), [0, 0, 0, 255]);
+        assert_eq!(gradient.sample(1.0), [255, 255, 255, 255]);
+        assert_eq!(ColorGradient::new([]).sample(0.5), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn sample_hits_the_stops_exactly() {
+        let gradient = ColorGradient::terrain();
+        for stop in gradient.stops() {
+            assert_eq!(gradient.sample(stop.position), stop.color);
+        }
+    }
+
+    #[test]
+    fn step_keeps_the_lower_stop() {
+        let gradient = black_to_white().with_interpolation(GradientInterpolation::Step);
+        assert_eq!(gradient.sample(0.2), [0, 0, 0, 255]);
+        assert_eq!(gradient.sample(0.59), [0, 0, 0, 255]);
+        assert_eq!(gradient.sample(0.6), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn linear_blending_is_brighter_than_srgb() {
+        let srgb = black_to_white().sample(0.4);
+        let linear = black_to_white().with_blend(BlendSpace::Linear).sample(0.4);
+        assert_eq!(srgb, [127, 127, 127, 255]);
+        // half the light is 188 in sRGB
+        assert_eq!(linear, [188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn deserialized_stops_are_sorted() {
+        let ron = "(stops: [(position: 1.0, color: (255, 255, 255, 255)), (position: 0.0, color: (0, 0, 0, 255))])";
+        let gradient = ColorGradient::from_ron(ron).unwrap();
+        assert_eq!(gradient, ColorGradient::grayscale());
+        let json = r#"{"stops": [{"position": 1.0, "color": [255, 255, 255, 255]}, {"position": 0.0, "color": [0, 0, 0, 255]}]}"#;
+        let gradient: ColorGradient = serde_json::from_str(json).unwrap();
+        assert_eq!(gradient, ColorGradient::grayscale());
+    }
+
+    #[test]
+    fn ron_and_json_round_trip() {
+        let gradient = ColorGradient::heat().with_interpolation(GradientInterpolation::Step);
+        let ron = ron::to_string(&gradient).unwrap();
+        assert_eq!(ColorGradient::from_ron(&ron).unwrap(), gradient);
+        let json = serde_json::to_string(&gradient).unwrap();
+        assert_eq!(ColorGradient::from_json(&json).unwrap(), gradient);
+    }
+}
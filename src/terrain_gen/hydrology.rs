@@ -0,0 +1,587 @@
+//! Rivers and lakes from the terrain heights. Depressions are filled with a
+//! priority-flood, water flows to the lowest of the 8 neighbours and rivers form
+//! where enough of it accumulates.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use bevy::{prelude::*, utils::hashbrown::HashMap};
+use serde::{Deserialize, Serialize};
+
+use super::{region::HeightField, ChunkId, TerrainConfig, TerrainMap};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HydrologySettings {
+    /// samples that have to drain through a sample before it is part of a river
+    pub river_threshold: u32,
+    /// how deep the smallest rivers are carved in world units, larger rivers are
+    /// carved deeper
+    pub carve_depth: f32,
+    /// lakes shallower than this in world units are ignored
+    pub min_lake_depth: f32,
+}
+
+impl Default for HydrologySettings {
+    fn default() -> Self {
+        Self {
+            river_threshold: 200,
+            carve_depth: 0.05,
+            min_lake_depth: 0.02,
+        }
+    }
+}
+
+pub struct River {
+    /// world positions on the water surface from the source downstream
+    pub points: Vec<Vec3>,
+    /// samples drained through the last point
+    pub flow: u32,
+}
+
+pub struct Lake {
+    /// world height of the water surface
+    pub water_level: f32,
+    /// global sample indices covered by the lake
+    pub samples: Vec<(isize, isize)>,
+}
+
+/// The rivers and lakes of the regions generated with [`TerrainMap::generate_hydrology`].
+#[derive(Resource, Default)]
+pub struct RiverNetwork {
+    pub rivers: Vec<River>,
+    pub lakes: Vec<Lake>,
+    /// river index of every river sample
+    river_samples: HashMap<(isize, isize), usize>,
+    /// lake index of every lake sample
+    lake_samples: HashMap<(isize, isize), usize>,
+    config: TerrainConfig,
+}
+
+impl RiverNetwork {
+    /// the river flowing through the sample nearest to the world (x, z) position
+    pub fn river_at(&self, position: Vec2) -> Option<&River> {
+        let index = self.river_samples.get(&self.nearest_sample(position))?;
+        self.rivers.get(*index)
+    }
+
+    pub fn lake_at(&self, position: Vec2) -> Option<&Lake> {
+        let index = self.lake_samples.get(&self.nearest_sample(position))?;
+        self.lakes.get(*index)
+    }
+
+    /// adds the rivers and lakes of another region, regions may overlap so the
+    /// rivers and lakes whose samples are all known already are left out
+    pub fn merge(&mut self, other: RiverNetwork) {
+        let mut river_samples = vec![Vec::new(); other.rivers.len()];
+        for (sample, river) in other.river_samples {
+            river_samples[river].push(sample);
+        }
+        merge_by_sample(
+            &mut self.rivers,
+            &mut self.river_samples,
+            other.rivers.into_iter().zip(river_samples),
+        );
+        let lakes = other.lakes.into_iter().map(|lake| {
+            let samples = lake.samples.clone();
+            (lake, samples)
+        });
+        merge_by_sample(&mut self.lakes, &mut self.lake_samples, lakes);
+        self.config = other.config;
+    }
+
+    /// global samples of all rivers and lakes
+    pub fn samples(&self) -> impl Iterator<Item = (isize, isize)> + '_ {
+        self.river_samples
+            .keys()
+            .chain(self.lake_samples.keys())
+            .copied()
+    }
+
+    fn nearest_sample(&self, position: Vec2) -> (isize, isize) {
+        // chunk (0, 0) is centered on the origin
+        let sample = (position + self.config.chunk_size / 2.0) / self.config.sample_spacing();
+        (sample.x.round() as isize, sample.y.round() as isize)
+    }
+}
+
+#[cfg(test)]
+impl RiverNetwork {
+    /// a network with one river through the global samples
+    pub(super) fn through(samples: &[(isize, isize)]) -> Self {
+        Self {
+            rivers: vec![River {
+                points: Vec::new(),
+                flow: 0,
+            }],
+            river_samples: samples.iter().map(|sample| (*sample, 0)).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Adds the items with at least one unknown sample. Known samples keep pointing to
+/// the item they already belong to.
+fn merge_by_sample<T>(
+    items: &mut Vec<T>,
+    index: &mut HashMap<(isize, isize), usize>,
+    other: impl IntoIterator<Item = (T, Vec<(isize, isize)>)>,
+) {
+    for (item, samples) in other {
+        let new: Vec<_> = samples
+            .into_iter()
+            .filter(|sample| !index.contains_key(sample))
+            .collect();
+        if new.is_empty() {
+            continue;
+        }
+        index.extend(new.into_iter().map(|sample| (sample, items.len())));
+        items.push(item);
+    }
+}
+
+/// min-heap entry of the priority-flood
+struct Cell {
+    height: f32,
+    index: usize,
+}
+
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Cell {}
+
+impl PartialOrd for Cell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, the lowest cell comes first
+        other
+            .height
+            .total_cmp(&self.height)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+/// Priority-flood after Barnes et al., raises every depression to its spill height.
+/// With an `epsilon` the filled surface keeps a tiny slope, so water can flow over it.
+fn priority_flood(field: &HeightField, epsilon: f32) -> Vec<f32> {
+    let mut filled = field.data.clone();
+    let mut done = vec![false; filled.len()];
+    let mut open = BinaryHeap::new();
+    for z in 0..field.height {
+        for x in 0..field.width {
+            if x == 0 || z == 0 || x == field.width - 1 || z == field.height - 1 {
+                let index = x + z * field.width;
+                done[index] = true;
+                open.push(Cell {
+                    height: filled[index],
+                    index,
+                });
+            }
+        }
+    }
+    while let Some(cell) = open.pop() {
+        for (neighbour, _) in field.neighbours(cell.index) {
+            if done[neighbour] {
+                continue;
+            }
+            done[neighbour] = true;
+            filled[neighbour] = filled[neighbour].max(cell.height + epsilon);
+            open.push(Cell {
+                height: filled[neighbour],
+                index: neighbour,
+            });
+        }
+    }
+    filled
+}
+
+struct Hydrology {
+    /// downstream neighbour of every sample, `None` where the water leaves the region
+    flow_direction: Vec<Option<usize>>,
+    /// samples draining through every sample, itself included
+    accumulation: Vec<u32>,
+    /// water surface of the filled depressions
+    water_level: Vec<f32>,
+}
+
+fn analyze(field: &HeightField) -> Hydrology {
+    let routing = priority_flood(field, 1e-5);
+    let water_level = priority_flood(field, 0.0);
+    let flow_direction: Vec<Option<usize>> = (0..field.data.len())
+        .map(|index| {
+            field
+                .neighbours(index)
+                .map(|(neighbour, distance)| {
+                    (neighbour, (routing[index] - routing[neighbour]) / distance)
+                })
+                .filter(|(_, drop)| *drop > 0.0)
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(neighbour, _)| neighbour)
+        })
+        .collect();
+    // pass the water down from the highest sample to the lowest
+    let mut order: Vec<usize> = (0..field.data.len()).collect();
+    order.sort_by(|a, b| routing[*b].total_cmp(&routing[*a]));
+    let mut accumulation = vec![1; field.data.len()];
+    for index in order {
+        if let Some(downstream) = flow_direction[index] {
+            accumulation[downstream] += accumulation[index];
+        }
+    }
+    Hydrology {
+        flow_direction,
+        accumulation,
+        water_level,
+    }
+}
+
+impl TerrainMap {
+    /// Fills the depressions of the chunks from `min` to `max` inclusive, extracts the
+    /// rivers and lakes and carves the river beds, see [`TerrainMap::process_region`].
+    /// The rivers and lakes are merged into `rivers`, e.g. the [`RiverNetwork`] resource,
+    /// and moisten the biomes around them once they are rebuilt.
+    pub fn generate_hydrology(
+        &mut self,
+        min: ChunkId,
+        max: ChunkId,
+        settings: &HydrologySettings,
+        rivers: &mut RiverNetwork,
+    ) -> Option<Vec<ChunkId>> {
+        let mut network = RiverNetwork {
+            config: self.config.clone(),
+            ..Default::default()
+        };
+        let field = self.processed_region(min, max, |field| {
+            let hydrology = analyze(field);
+            extract_lakes(field, &hydrology, settings, &mut network);
+            extract_rivers(field, &hydrology, settings, &mut network);
+            carve_rivers(field, &hydrology, settings, &network);
+        })?;
+        rivers.merge(network);
+        Some(self.apply_height_field(&field))
+    }
+}
+
+fn global_sample(field: &HeightField, index: usize) -> (isize, isize) {
+    (
+        field.origin.0 + (index % field.width) as isize,
+        field.origin.1 + (index / field.width) as isize,
+    )
+}
+
+fn extract_lakes(
+    field: &HeightField,
+    hydrology: &Hydrology,
+    settings: &HydrologySettings,
+    network: &mut RiverNetwork,
+) {
+    let min_depth = settings.min_lake_depth / field.height_scale;
+    let flooded = |index: usize| hydrology.water_level[index] - field.data[index] > 0.0;
+    let mut visited = vec![false; field.data.len()];
+    for start in 0..field.data.len() {
+        if visited[start] || !flooded(start) {
+            continue;
+        }
+        // flood fill the basin, all of it has the same water level
+        let mut samples = Vec::new();
+        let mut depth: f32 = 0.0;
+        let mut stack = vec![start];
+        visited[start] = true;
+        while let Some(index) = stack.pop() {
+            samples.push(index);
+            depth = depth.max(hydrology.water_level[index] - field.data[index]);
+            for (neighbour, _) in field.neighbours(index) {
+                if !visited[neighbour] && flooded(neighbour) {
+                    visited[neighbour] = true;
+                    stack.push(neighbour);
+                }
+            }
+        }
+        if depth < min_depth {
+            continue;
+        }
+        let lake = network.lakes.len();
+        let samples: Vec<_> = samples
+            .into_iter()
+            .map(|index| global_sample(field, index))
+            .collect();
+        for sample in samples.iter() {
+            network.lake_samples.insert(*sample, lake);
+        }
+        network.lakes.push(Lake {
+            water_level: hydrology.water_level[start] * field.height_scale,
+            samples,
+        });
+    }
+}
+
+fn extract_rivers(
+    field: &HeightField,
+    hydrology: &Hydrology,
+    settings: &HydrologySettings,
+    network: &mut RiverNetwork,
+) {
+    let is_river = |index: usize| hydrology.accumulation[index] >= settings.river_threshold;
+    // a river starts where no river flows in
+    let mut has_inflow = vec![false; field.data.len()];
+    for (index, downstream) in hydrology.flow_direction.iter().enumerate() {
+        if let Some(downstream) = downstream {
+            if is_river(index) {
+                has_inflow[*downstream] = true;
+            }
+        }
+    }
+    let half_size = network.config.chunk_size / 2.0;
+    let world = |index: usize| {
+        let (x, z) = global_sample(field, index);
+        Vec3::new(
+            x as f32 * field.sample_spacing - half_size,
+            hydrology.water_level[index] * field.height_scale,
+            z as f32 * field.sample_spacing - half_size,
+        )
+    };
+    let mut visited = vec![false; field.data.len()];
+    for (source, has_inflow) in has_inflow.into_iter().enumerate() {
+        if !is_river(source) || has_inflow {
+            continue;
+        }
+        let river = network.rivers.len();
+        let mut points = Vec::new();
+        let mut current = Some(source);
+        let mut flow = 0;
+        // follow the water until it leaves the region or joins a known river
+        while let Some(index) = current {
+            points.push(world(index));
+            flow = hydrology.accumulation[index];
+            if visited[index] {
+                break;
+            }
+            visited[index] = true;
+            network
+                .river_samples
+                .insert(global_sample(field, index), river);
+            current = hydrology.flow_direction[index];
+        }
+        network.rivers.push(River { points, flow });
+    }
+}
+
+fn carve_rivers(
+    field: &mut HeightField,
+    hydrology: &Hydrology,
+    settings: &HydrologySettings,
+    network: &RiverNetwork,
+) {
+    let depth = settings.carve_depth / field.height_scale;
+    for index in 0..field.data.len() {
+        let sample = global_sample(field, index);
+        if !network.river_samples.contains_key(&sample)
+            || network.lake_samples.contains_key(&sample)
+        {
+            continue;
+        }
+        // larger rivers cut deeper beds
+        let size = hydrology.accumulation[index] as f32 / settings.river_threshold as f32;
+        field.data[index] -= depth * size.sqrt().min(4.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a 5x5 basin with a floor at 0.2, a rim at 1.0 and an outlet at 0.8 on the left
+    fn basin() -> HeightField {
+        let mut data = vec![0.2; 25];
+        for z in 0..5 {
+            for x in 0..5 {
+                if x == 0 || z == 0 || x == 4 || z == 4 {
+                    data[x + z * 5] = 1.0;
+                }
+            }
+        }
+        data[2 * 5] = 0.8;
+        data[2 + 2 * 5] = 0.1;
+        HeightField {
+            width: 5,
+            height: 5,
+            origin: (0, 0),
+            data,
+            sample_spacing: 1.0,
+            height_scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn priority_flood_fills_to_the_spill_height() {
+        let field = basin();
+        let filled = priority_flood(&field, 0.0);
+        for z in 0..5 {
+            for x in 0..5 {
+                let index = x + z * 5;
+                let border = x == 0 || z == 0 || x == 4 || z == 4;
+                let expected = if border { field.data[index] } else { 0.8 };
+                assert_eq!(filled[index], expected, "({x}, {z})");
+            }
+        }
+    }
+
+    #[test]
+    fn priority_flood_with_epsilon_drains_every_sample() {
+        let field = basin();
+        let filled = priority_flood(&field, 1e-3);
+        for z in 1..4 {
+            for x in 1..4 {
+                let index = x + z * 5;
+                assert!(filled[index] > 0.8);
+                assert!(field
+                    .neighbours(index)
+                    .any(|(neighbour, _)| filled[neighbour] < filled[index]));
+            }
+        }
+    }
+
+    #[test]
+    fn priority_flood_keeps_terrain_without_depressions() {
+        let mut field = basin();
+        for z in 0..5 {
+            for x in 0..5 {
+                field.set(x, z, x as f32 * 0.1 + z as f32 * 0.01);
+            }
+        }
+        assert_eq!(priority_flood(&field, 0.0), field.data);
+    }
+
+    #[test]
+    fn all_water_leaves_through_the_outlet() {
+        let hydrology = analyze(&basin());
+        let outlet = 2 * 5;
+        assert_eq!(hydrology.flow_direction[outlet], None);
+        assert_eq!(hydrology.accumulation[outlet], 25);
+        assert_eq!(hydrology.water_level[2 + 2 * 5], 0.8);
+    }
+
+    /// a 7x12 valley along z that falls towards z = 0
+    fn valley() -> HeightField {
+        let (width, height) = (7, 12);
+        let data = (0..width * height)
+            .map(|index| {
+                let (x, z) = (index % width, index / width);
+                (x as f32 - 3.0).abs() * 0.1 + z as f32 * 0.05
+            })
+            .collect();
+        HeightField {
+            width,
+            height,
+            origin: (0, 0),
+            data,
+            sample_spacing: 1.0,
+            height_scale: 1.0,
+        }
+    }
+
+    fn settings() -> HydrologySettings {
+        HydrologySettings {
+            river_threshold: 10,
+            carve_depth: 0.05,
+            min_lake_depth: 0.02,
+        }
+    }
+
+    #[test]
+    fn rivers_start_above_the_threshold() {
+        let field = valley();
+        let hydrology = analyze(&field);
+        let mut network = RiverNetwork::default();
+        extract_rivers(&field, &hydrology, &settings(), &mut network);
+        assert_eq!(network.rivers.len(), 1);
+        for index in 0..field.data.len() {
+            let above = hydrology.accumulation[index] >= settings().river_threshold;
+            let sample = global_sample(&field, index);
+            assert_eq!(network.river_samples.contains_key(&sample), above);
+        }
+        // the river runs down the valley floor and leaves at the low end
+        assert!(network.river_samples.contains_key(&(3, 0)));
+        let river = &network.rivers[0];
+        assert_eq!(river.flow, hydrology.accumulation[3]);
+        assert!(river.points.windows(2).all(|pair| pair[1].y <= pair[0].y));
+    }
+
+    #[test]
+    fn lakes_fill_the_basin_up_to_the_spill_height() {
+        let field = basin();
+        let hydrology = analyze(&field);
+        let mut network = RiverNetwork::default();
+        extract_lakes(&field, &hydrology, &settings(), &mut network);
+        assert_eq!(network.lakes.len(), 1);
+        let lake = &network.lakes[0];
+        assert_eq!(lake.water_level, 0.8);
+        assert_eq!(lake.samples.len(), 9);
+        assert!(lake
+            .samples
+            .iter()
+            .all(|(x, z)| (1..4).contains(x) && (1..4).contains(z)));
+        // shallow lakes are ignored
+        let deep_only = HydrologySettings {
+            min_lake_depth: 1.0,
+            ..settings()
+        };
+        let mut network = RiverNetwork::default();
+        extract_lakes(&field, &hydrology, &deep_only, &mut network);
+        assert!(network.lakes.is_empty());
+    }
+
+    #[test]
+    fn only_river_beds_are_carved() {
+        let mut field = valley();
+        let original = field.data.clone();
+        let hydrology = analyze(&field);
+        let mut network = RiverNetwork::default();
+        extract_rivers(&field, &hydrology, &settings(), &mut network);
+        carve_rivers(&mut field, &hydrology, &settings(), &network);
+        for (index, (carved, original)) in field.data.iter().zip(&original).enumerate() {
+            let sample = global_sample(&field, index);
+            if network.river_samples.contains_key(&sample) {
+                assert!(carved < original);
+            } else {
+                assert_eq!(carved, original);
+            }
+        }
+        // larger rivers cut deeper
+        let cut = |index: usize| original[index] - field.data[index];
+        assert!(cut(3) > cut(3 + 7 * (field.height - 3)));
+    }
+
+    #[test]
+    fn merging_overlapping_regions_keeps_one_copy() {
+        let extract = |field: &HeightField| {
+            let hydrology = analyze(field);
+            let mut network = RiverNetwork::default();
+            extract_lakes(field, &hydrology, &settings(), &mut network);
+            extract_rivers(field, &hydrology, &settings(), &mut network);
+            network
+        };
+        let mut merged = RiverNetwork::default();
+        merged.merge(extract(&basin()));
+        merged.merge(extract(&basin()));
+        assert_eq!(merged.lakes.len(), 1);
+        let rivers = merged.rivers.len();
+        merged.merge(extract(&valley()));
+        merged.merge(extract(&valley()));
+        assert_eq!(merged.rivers.len(), rivers + 1);
+        // a region elsewhere is added
+        let mut moved = valley();
+        moved.origin = (100, 0);
+        merged.merge(extract(&moved));
+        assert_eq!(merged.rivers.len(), rivers + 2);
+        assert!(merged.river_samples.contains_key(&(103, 0)));
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use bevy::{log, prelude::*, utils::hashbrown::HashMap};
+
+use super::{
+    terrain_noise, ChunkId, ChunkUnloadSettings, DespawnTerrainMeshEvent, RiverNetwork,
+    SpawnTerrainMeshEvent, TerrainMap,
+};
+use crate::util::noise::{FractalSettings, WarpSettings};
+
+/// Loads the chunks around the entity's [`GlobalTransform`], on the x-z plane.
+/// Any number of loaders can be active at once.
+#[derive(Component, Clone, Debug)]
+pub struct ChunkLoader {
+    /// load radius in chunks
+    pub radius: u32,
+    /// chunks of loaders with a higher priority are requested first
+    pub priority: i32,
+}
+
+impl ChunkLoader {
+    pub fn new(radius: u32) -> Self {
+        Self {
+            radius,
+            priority: 0,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+fn distance_squared(a: ChunkId, b: ChunkId) -> isize {
+    (a.0 - b.0).pow(2) + (a.1 - b.1).pow(2)
+}
+
+/// Requests the missing chunks around all loaders, highest priority and nearest first.
+pub fn load_chunks(
+    mut event: EventWriter<SpawnTerrainMeshEvent>,
+    mut map: ResMut<TerrainMap>,
+    settings: Res<FractalSettings>,
+    warp: Option<Res<WarpSettings>>,
+    rivers: Res<RiverNetwork>,
+    q_loader: Query<(&ChunkLoader, &GlobalTransform)>,
+) {
+    // the best (priority, distance) of every chunk in range of a loader
+    let mut requested: HashMap<ChunkId, (i32, isize)> = HashMap::new();
+    for (loader, transform) in q_loader.iter() {
+        let center = map.config.chunk_at(transform.translation());
+        let radius = loader.radius as isize;
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                let id = (center.0 + x, center.1 + z);
+                let distance = distance_squared(id, center);
+                if distance > radius * radius || map.is_loaded(id) || map.pending.contains_key(&id)
+                {
+                    continue;
+                }
+                let key = (-loader.priority, distance);
+                requested
+                    .entry(id)
+                    .and_modify(|best| *best = (*best).min(key))
+                    .or_insert(key);
+            }
+        }
+    }
+    if requested.is_empty() {
+        return;
+    }
+    let mut requested: Vec<_> = requested.into_iter().collect();
+    requested.sort_by_key(|(_, key)| *key);
+
+    let noise = Arc::new(terrain_noise(&map, &settings, warp.as_deref()));
+    for (id, _) in requested {
+        if map.restore_cached(id) {
+            event.send(SpawnTerrainMeshEvent(id));
+            log::info!("Restored chunk: {:?}", id);
+        } else if map.request_chunk(id, &noise, &rivers) {
+            log::info!("Requested chunk: {:?}", id);
+        }
+    }
+}
+
+/// Unloads the chunks that are out of the unload range of every loader.
+pub fn unload_chunks(
+    mut event: EventWriter<DespawnTerrainMeshEvent>,
+    mut map: ResMut<TerrainMap>,
+    settings: Res<ChunkUnloadSettings>,
+    q_loader: Query<(&ChunkLoader, &GlobalTransform)>,
+) {
+    let loaders: Vec<(ChunkId, isize)> = q_loader
+        .iter()
+        .map(|(loader, transform)| {
+            let unload_range = loader.radius as isize + settings.hysteresis;
+            (map.config.chunk_at(transform.translation()), unload_range)
+        })
+        .collect();
+    let far: Vec<_> = map
+        .chunks
+        .keys()
+        .chain(map.pending.keys())
+        .filter(|id| {
+            loaders
+                .iter()
+                .all(|(center, range)| distance_squared(**id, *center) > range * range)
+        })
+        .copied()
+        .collect();
+    for id in far {
+        if map.unload_chunk(id) {
+            event.send(DespawnTerrainMeshEvent(id));
+            log::info!("Unloaded chunk: {:?}", id);
+        }
+    }
+    map.evict_cached(settings.cache_capacity);
+}
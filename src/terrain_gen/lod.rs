@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+
+use super::{create_terrain_mesh, ChunkId, TerrainConfig, TerrainMap};
+
+/// Camera distances at which the chunk meshes switch to a lower level of detail.
+#[derive(Resource)]
+pub struct LodSettings {
+    /// up to `distances[0]` the full resolution is used, then 1/2, 1/4 and beyond
+    /// the last distance 1/8
+    pub distances: [f32; 3],
+    /// how far the skirts hang below the chunk borders
+    pub skirt_depth: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            distances: [10.0, 20.0, 40.0],
+            skirt_depth: 0.5,
+        }
+    }
+}
+
+impl LodSettings {
+    /// the level of detail of a chunk, 0 is the full resolution
+    pub fn lod(&self, camera: Vec3, id: ChunkId, config: &TerrainConfig) -> usize {
+        let center = Vec2::new(id.0 as f32, id.1 as f32) * config.chunk_size;
+        let distance = center.distance(Vec2::new(camera.x, camera.z));
+        self.distances
+            .iter()
+            .position(|max| distance <= *max)
+            .unwrap_or(self.distances.len())
+    }
+}
+
+/// The mesh of a chunk and its level of detail.
+#[derive(Component)]
+pub struct ChunkMesh {
+    pub id: ChunkId,
+    pub lod: usize,
+}
+
+/// Re-meshes the chunks whose level of detail changed, the height maps are reused.
+pub fn update_terrain_lod(
+    map: Res<TerrainMap>,
+    settings: Res<LodSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    mut q_chunk: Query<(&mut ChunkMesh, &mut Handle<Mesh>)>,
+) {
+    let Some((_, camera)) = q_camera.iter().find(|(camera, _)| camera.is_active) else {
+        return;
+    };
+    for (mut chunk_mesh, mut handle) in q_chunk.iter_mut() {
+        let lod = settings.lod(camera.translation(), chunk_mesh.id, &map.config);
+        if lod == chunk_mesh.lod {
+            continue;
+        }
+        let Some(chunk) = map.chunks.get(&chunk_mesh.id) else {
+            continue;
+        };
+        let mesh = create_terrain_mesh(
+            &chunk.height_map,
+            Some(&chunk.biome_map),
+            &map.config,
+            lod,
+            settings.skirt_depth,
+        );
+        *handle = meshes.add(mesh);
+        chunk_mesh.lod = lod;
+    }
+}
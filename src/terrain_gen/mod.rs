@@ -1,6 +1,20 @@
+mod biome;
+mod erosion;
+mod generation;
+mod gradient;
+mod hydrology;
+mod loading;
+mod lod;
+mod normals;
 pub mod pixels;
+mod quadtree;
+mod query;
+mod raycast;
+mod region;
+mod unloading;
+mod water;
 
-use std::{isize, usize};
+use std::collections::VecDeque;
 
 use bevy::{
     color::palettes::css::{BLACK, GREEN},
@@ -11,25 +25,85 @@ use bevy::{
         mesh::{Indices, PrimitiveTopology},
         render_asset::RenderAssetUsages,
     },
-    utils::hashbrown::HashMap,
+    utils::hashbrown::{HashMap, HashSet},
 };
 use bevy_egui::{egui, EguiContexts, EguiUserTextures};
+pub use biome::{
+    rebuild_changed_biomes, whittaker, Biome, BiomeMap, BiomeSettings, ResourceDeposit,
+    ResourceKind,
+};
+pub use erosion::{HydraulicErosion, PostProcess, ThermalErosion};
+pub use generation::{finish_pending_chunks, ChunkGenerationBudget, PendingChunk};
+pub use gradient::{
+    BlendSpace, ColorGradient, ColorGradientLoader, GradientInterpolation, GradientLoadError,
+    GradientStop,
+};
+pub use hydrology::{HydrologySettings, Lake, River, RiverNetwork};
+pub use loading::{load_chunks, unload_chunks, ChunkLoader};
+pub use lod::{update_terrain_lod, ChunkMesh, LodSettings};
+pub use normals::remesh_changed_chunks;
 use pixels::PixelData;
-
-use crate::util::noise::PerlinNoise;
+pub use quadtree::{update_terrain_quadtree, QuadTreeSettings, TerrainQuadTree};
+pub use query::HeightInterpolation;
+pub use raycast::{update_terrain_cursor, TerrainCursor, TerrainHit};
+pub use region::HeightField;
+pub use unloading::{despawn_terrain_meshes, ChunkUnloadSettings};
+pub use water::{Surface, WaterMesh};
+
+use crate::util::noise::{
+    FractalKind, FractalSettings, NoiseNode, NoiseSource, Period, PerlinNoise, Tiled, WarpSettings,
+};
 
 type ChunkId = (isize, isize);
 
+/// Streams the chunks around every [`ChunkLoader`]. Showing the chunks is up to the
+/// app, e.g. with [`spawn_terrain_plain`] on [`SpawnTerrainMeshEvent`].
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SpawnTerrainMeshEvent>()
+            .add_event::<DespawnTerrainMeshEvent>()
+            .init_asset::<ColorGradient>()
+            .init_asset_loader::<ColorGradientLoader>()
+            .init_resource::<TerrainConfig>()
+            .init_resource::<FractalSettings>()
+            .init_resource::<ChunkGenerationBudget>()
+            .init_resource::<ChunkUnloadSettings>()
+            .init_resource::<TerrainCursor>()
+            .init_resource::<LodSettings>()
+            .init_resource::<RiverNetwork>()
+            .add_systems(Startup, spawn_terrain_map)
+            .add_systems(
+                Update,
+                (
+                    load_chunks,
+                    unload_chunks,
+                    finish_pending_chunks,
+                    rebuild_changed_biomes,
+                    remesh_changed_chunks,
+                    despawn_terrain_meshes,
+                )
+                    .chain(),
+            )
+            .add_systems(Update, update_terrain_cursor);
+        app.world().resource::<TerrainConfig>().validate();
+    }
+}
+
 pub fn debug_show_terrain_normals(mut gizmos: Gizmos, map: Res<TerrainMap>) {
     let color = GREEN;
+    let config = &map.config;
+    let size = config.chunk_size;
     for (id, chunk) in map.chunks.iter() {
-        for z in 0..HEIGHT_MAP_SIZE {
-            for x in 0..HEIGHT_MAP_SIZE {
-                let x_scale = x as f32 / (HEIGHT_MAP_SIZE - 1) as f32;
-                let xf = x_scale * CHUNK_SIZE + id.0 as f32 * CHUNK_SIZE - CHUNK_SIZE / 2.0;
-                let z_scale = z as f32 / (HEIGHT_MAP_SIZE - 1) as f32;
-                let zf = z_scale * CHUNK_SIZE + id.1 as f32 * CHUNK_SIZE - CHUNK_SIZE / 2.0;
-                let y = chunk.height_map.get(x, z) * 2.0;
+        let samples = chunk.height_map.size();
+        for z in 0..samples {
+            for x in 0..samples {
+                let x_scale = x as f32 / (samples - 1) as f32;
+                let xf = x_scale * size + id.0 as f32 * size - size / 2.0;
+                let z_scale = z as f32 / (samples - 1) as f32;
+                let zf = z_scale * size + id.1 as f32 * size - size / 2.0;
+                let y = chunk.height_map.get(x, z) * config.height_scale;
                 let start = Vec3::new(xf, y, zf);
                 let normal = chunk.height_map.get_normal(x, z) * 0.2;
                 let end = normal + start;
@@ -41,83 +115,263 @@ pub fn debug_show_terrain_normals(mut gizmos: Gizmos, map: Res<TerrainMap>) {
 
 #[derive(Resource)]
 pub struct TerrainMap {
+    /// the loaded chunks
     pub chunks: HashMap<ChunkId, Chunk>,
+    /// chunks that are still being generated
+    pub pending: HashMap<ChunkId, PendingChunk>,
+    /// unloaded chunks whose height maps are kept for when they are loaded again
+    pub cached: HashMap<ChunkId, Chunk>,
+    /// the keys of `cached`, least recently used first
+    pub cache_order: VecDeque<ChunkId>,
+    /// the mesh or sprite spawned for each loaded chunk
+    pub entities: HashMap<ChunkId, Entity>,
+    /// chunks whose heights or normals changed since they were meshed
+    pub changed: HashSet<ChunkId>,
+    /// chunks whose biomes have to be rebuilt from their heights and the rivers, see
+    /// [`rebuild_changed_biomes`]
+    pub biomes_changed: HashSet<ChunkId>,
+    pub config: TerrainConfig,
 }
 
-#[derive(Event, Deref, Debug)]
-pub struct SpawnTerrainMeshEvent(pub ChunkId);
+/// Resolution and scale of the terrain, copied into the [`TerrainMap`] when it is created.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct TerrainConfig {
+    /// world units per chunk side
+    pub chunk_size: f32,
+    /// height map samples per chunk side, neighbouring chunks share their border samples
+    pub samples: usize,
+    /// scales the height map values to world units
+    pub height_scale: f32,
+    /// world height of the water surface
+    pub sea_level: f32,
+    /// water deeper than this below the sea level is deep water
+    pub shallow_depth: f32,
+    /// land up to this above the sea level is shore
+    pub shore_height: f32,
+    pub biomes: BiomeSettings,
+    /// the same seed always gives the same terrain
+    pub seed: u64,
+    /// how the world wraps around at its borders
+    pub wrap: WorldWrap,
+}
 
-const CHUNK_SIZE: f32 = 5.0;
-pub struct Chunk {
-    pub height_map: HeightMap,
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 5.0,
+            samples: 62,
+            height_scale: 2.0,
+            sea_level: -0.4,
+            shallow_depth: 0.3,
+            shore_height: 0.1,
+            biomes: BiomeSettings::default(),
+            seed: DEFAULT_SEED,
+            wrap: WorldWrap::None,
+        }
+    }
 }
 
-const HEIGHT_MAP_SIZE: usize = 62;
-pub struct HeightMap {
-    height_data: [f32; HEIGHT_MAP_SIZE * HEIGHT_MAP_SIZE],
-    normal: [Vec3; HEIGHT_MAP_SIZE * HEIGHT_MAP_SIZE],
+impl TerrainConfig {
+    /// Panics if the config can't describe a terrain, a chunk needs at least one cell
+    /// and a positive size, a wrapping world at least one chunk.
+    pub fn validate(&self) {
+        assert!(
+            self.samples >= 2,
+            "a chunk needs at least 2 samples per side, got {}",
+            self.samples
+        );
+        assert!(
+            self.chunk_size > 0.0,
+            "the chunk size must be positive, got {}",
+            self.chunk_size
+        );
+        match self.wrap {
+            WorldWrap::None => {}
+            WorldWrap::Cylindrical { width } => {
+                assert!(width > 0, "the wrap width must be positive, got {width}")
+            }
+            WorldWrap::Toroidal { width, height } => assert!(
+                width > 0 && height > 0,
+                "the wrap size must be positive, got {width}x{height}"
+            ),
+        }
+    }
+
+    /// world units between two samples
+    pub fn sample_spacing(&self) -> f32 {
+        self.chunk_size / (self.samples - 1) as f32
+    }
+
+    /// the chunk that contains the world position
+    pub fn chunk_at(&self, position: Vec3) -> ChunkId {
+        (
+            (position.x / self.chunk_size).round() as isize,
+            (position.z / self.chunk_size).round() as isize,
+        )
+    }
+}
+
+impl TerrainMap {
+    /// the chunk whose terrain is shown at `id`
+    pub fn canonical_id(&self, id: ChunkId) -> ChunkId {
+        self.config.wrap.canonical_id(id)
+    }
 }
 
-fn get_index(x: usize, y: usize) -> usize {
-    assert!(x < HEIGHT_MAP_SIZE);
-    assert!(y < HEIGHT_MAP_SIZE);
-    y + x * HEIGHT_MAP_SIZE
+/// How the world wraps around at its borders. Sizes are in chunks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WorldWrap {
+    #[default]
+    None,
+    /// wraps east-west
+    Cylindrical { width: isize },
+    /// wraps east-west and north-south
+    Toroidal { width: isize, height: isize },
 }
 
-fn calculate_normal(id: ChunkId, perlin: &PerlinNoise) -> [Vec3; HEIGHT_MAP_SIZE.pow(2)] {
-    let mut normals = [Vec3::ZERO; HEIGHT_MAP_SIZE.pow(2)];
-    let x_offset = (HEIGHT_MAP_SIZE as isize - 1) * id.0;
-    let y_offset = (HEIGHT_MAP_SIZE as isize - 1) * id.1;
-    let d = 0.1;
-    for y in 0..HEIGHT_MAP_SIZE {
-        for x in 0..HEIGHT_MAP_SIZE {
-            let xf = (x as isize + x_offset) as f32;
-            let yf = (y as isize + y_offset) as f32;
-
-            let t = perlin.fractal_brownian_motion(xf, yf + d);
-            let b = perlin.fractal_brownian_motion(xf, yf - d);
-            let l = perlin.fractal_brownian_motion(xf + d, yf);
-            let r = perlin.fractal_brownian_motion(xf - d, yf);
-
-            let step = 2.0 * d * CHUNK_SIZE / HEIGHT_MAP_SIZE as f32;
-            let x_dir = Vec3::new(step, l - r, 0.0);
-            let z_dir = Vec3::new(0.0, t - b, step);
-            let normal = z_dir.cross(x_dir).normalize();
-
-            let index = get_index(x, y);
-            normals[index] = normal;
+impl WorldWrap {
+    pub fn canonical_id(&self, id: ChunkId) -> ChunkId {
+        match *self {
+            WorldWrap::None => id,
+            WorldWrap::Cylindrical { width } => (id.0.rem_euclid(width), id.1),
+            WorldWrap::Toroidal { width, height } => {
+                (id.0.rem_euclid(width), id.1.rem_euclid(height))
+            }
         }
     }
-    normals
+
+    /// period of the terrain noise in sample units, `None` if the world doesn't wrap
+    pub fn noise_period(&self, samples_per_chunk: usize) -> Option<Period> {
+        let samples = |chunks: isize| Some((chunks * (samples_per_chunk as isize - 1)) as f32);
+        match *self {
+            WorldWrap::None => None,
+            WorldWrap::Cylindrical { width } => Some(Period::new(samples(width), None)),
+            WorldWrap::Toroidal { width, height } => {
+                Some(Period::new(samples(width), samples(height)))
+            }
+        }
+    }
+}
+
+/// Seed of the shuffled permutation table, the terrain differs from the one of the
+/// unseeded [`PerlinNoise::new`] with Ken Perlin's table.
+const DEFAULT_SEED: u64 = 0;
+
+/// Root of the noise graph used for the terrain height, optionally domain warped.
+/// For a wrapping world all noise fields are periodic, so the terrain is seamless.
+pub fn terrain_noise(
+    map: &TerrainMap,
+    settings: &FractalSettings,
+    warp: Option<&WarpSettings>,
+) -> NoiseNode {
+    let seed = map.config.seed;
+    let period = map.config.wrap.noise_period(map.config.samples);
+    let fbm = |seed: u64, settings: &FractalSettings| match period {
+        Some(period) => NoiseNode::source(Tiled::new(
+            PerlinNoise::with_seed(seed),
+            FractalKind::Fbm,
+            settings.clone(),
+            period,
+        )),
+        None => NoiseNode::perlin(seed).fbm(settings.clone()),
+    };
+    let height = fbm(seed, settings);
+    let Some(warp) = warp else {
+        return height;
+    };
+    let warp_x = fbm(seed.wrapping_add(1), &warp.fractal);
+    let warp_y = fbm(seed.wrapping_add(2), &warp.fractal);
+    height.recursive_warp(warp_x, warp_y, warp.strength, warp.iterations)
+}
+
+#[derive(Event, Deref, Debug)]
+pub struct SpawnTerrainMeshEvent(pub ChunkId);
+
+#[derive(Event, Deref, Debug)]
+pub struct DespawnTerrainMeshEvent(pub ChunkId);
+
+pub struct Chunk {
+    pub height_map: HeightMap,
+    pub biome_map: BiomeMap,
+}
+
+pub struct HeightMap {
+    /// samples per side
+    size: usize,
+    height_data: Vec<f32>,
+    normal: Vec<Vec3>,
 }
 
 impl HeightMap {
-    pub fn new(id: ChunkId) -> Self {
-        let perlin = PerlinNoise::new();
-        let mut height_data = [0.0; HEIGHT_MAP_SIZE * HEIGHT_MAP_SIZE];
-        for y in 0..HEIGHT_MAP_SIZE {
-            for x in 0..HEIGHT_MAP_SIZE {
-                let index = get_index(x, y);
-                let xf = (x as isize + ((HEIGHT_MAP_SIZE as isize - 1) * id.0)) as f32;
-                let yf = (y as isize + ((HEIGHT_MAP_SIZE as isize - 1) * id.1)) as f32;
-                let height = perlin.fractal_brownian_motion(xf, yf);
-                height_data[index] = height;
+    pub fn new<N: NoiseSource + ?Sized>(id: ChunkId, noise: &N, config: &TerrainConfig) -> Self {
+        let cells = (config.samples - 1) as f32;
+        let origin = Vec2::new(id.0 as f32, id.1 as f32) * cells;
+        Self::patch(origin, 1.0, noise, config)
+    }
+
+    /// A height map whose first sample is at `origin` and whose samples are `scale`
+    /// apart, both in the sample units of a chunk. Larger scales cover more terrain
+    /// with the same number of samples.
+    ///
+    /// The heights are evaluated as one grid with [`NoiseSource::fill_grid`], with a one
+    /// sample apron for the central difference normals. The apron holds the heights
+    /// of the neighbouring chunks, so the normals match at the seams.
+    pub fn patch<N: NoiseSource + ?Sized>(
+        origin: Vec2,
+        scale: f32,
+        noise: &N,
+        config: &TerrainConfig,
+    ) -> Self {
+        let size = config.samples;
+        let apron = size + 2;
+        let mut grid = vec![0.0; apron * apron];
+        noise.fill_grid(origin - scale, Vec2::splat(scale), apron, apron, &mut grid);
+        // the grid is row major with the rows along y, the apron shifts it by one
+        let grid = |x: isize, y: isize| grid[(x + 1) as usize + (y + 1) as usize * apron];
+
+        let mut height_data = vec![0.0; size * size];
+        let mut normal = vec![Vec3::ZERO; size * size];
+        // the noise is sampled in chunk sample units, whatever the scale
+        let slope_scale = config.height_scale / (config.sample_spacing() * scale);
+        for x in 0..size as isize {
+            for y in 0..size as isize {
+                let index = y as usize + x as usize * size;
+                height_data[index] = grid(x, y);
+                let dx = (grid(x + 1, y) - grid(x - 1, y)) / 2.0;
+                let dy = (grid(x, y + 1) - grid(x, y - 1)) / 2.0;
+                normal[index] = Vec3::new(-dx * slope_scale, 1.0, -dy * slope_scale).normalize();
             }
         }
-        let normal = calculate_normal(id, &perlin);
         Self {
+            size,
             height_data,
             normal,
         }
     }
 
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn get_index(&self, x: usize, y: usize) -> usize {
+        assert!(x < self.size);
+        assert!(y < self.size);
+        y + x * self.size
+    }
+
     fn get(&self, x: usize, y: usize) -> f32 {
-        let index = get_index(x, y);
+        let index = self.get_index(x, y);
         self.height_data[index]
     }
 
+    /// changes a height, the normals are updated by [`TerrainMap::heights_changed`]
+    pub fn set(&mut self, x: usize, y: usize, height: f32) {
+        let index = self.get_index(x, y);
+        self.height_data[index] = height;
+    }
+
     fn get_normal(&self, x: usize, y: usize) -> Vec3 {
-        let index = get_index(x, y);
+        let index = self.get_index(x, y);
         self.normal[index]
         // if x <= 0 || y <= 0 {
         //     return Vec3::X;
@@ -140,15 +394,16 @@ impl HeightMap {
     }
 }
 
-pub fn spawn_terrain_map(mut event: EventWriter<SpawnTerrainMeshEvent>, mut cmd: Commands) {
-    let mut chunks = HashMap::new();
+pub fn spawn_terrain_map(mut cmd: Commands, config: Res<TerrainConfig>) {
+    config.validate();
+    let chunks = HashMap::new();
     // for x in -2..=2 {
     //     for z in -2..=2 {
     //         let id = (x, z);
     //         chunks.insert(
     //             id,
     //             Chunk {
-    //                 height_map: HeightMap::new(id),
+    //                 height_map: HeightMap::new(id, &noise),
     //             },
     //         );
     //         event.send(SpawnTerrainMeshEvent(id));
@@ -156,76 +411,153 @@ pub fn spawn_terrain_map(mut event: EventWriter<SpawnTerrainMeshEvent>, mut cmd:
     //     }
     // }
 
-    let map = TerrainMap { chunks };
+    let map = TerrainMap {
+        chunks,
+        pending: HashMap::new(),
+        cached: HashMap::new(),
+        cache_order: VecDeque::new(),
+        entities: HashMap::new(),
+        changed: HashSet::new(),
+        biomes_changed: HashSet::new(),
+        config: config.clone(),
+    };
     cmd.insert_resource(map);
 }
 
+/// The materials shared by every chunk mesh of [`spawn_terrain_plain`].
+#[derive(Resource)]
+pub struct TerrainMaterials {
+    /// the biomes color the vertices
+    pub terrain: Handle<StandardMaterial>,
+    pub water: Handle<StandardMaterial>,
+}
+
+impl FromWorld for TerrainMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        Self {
+            terrain: materials.add(StandardMaterial::default()),
+            water: materials.add(StandardMaterial {
+                base_color: Color::srgba_u8(0, 50, 200, 180),
+                alpha_mode: AlphaMode::Blend,
+                ..default()
+            }),
+        }
+    }
+}
+
 pub fn spawn_terrain_plain(
     mut cmd: Commands,
     mut event: EventReader<SpawnTerrainMeshEvent>,
-    terrain_map: Res<TerrainMap>,
+    mut terrain_map: ResMut<TerrainMap>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    materials: Res<TerrainMaterials>,
+    lod_settings: Res<LodSettings>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
 ) {
+    let camera = q_camera
+        .iter()
+        .find(|(camera, _)| camera.is_active)
+        .map_or(Vec3::ZERO, |(_, transform)| transform.translation());
     for ev in event.read() {
         log::info!("Spawn Mesh for id {:?}", ev.0);
+        // the chunk might have been unloaded in the meantime
+        if !terrain_map.is_loaded(ev.0) {
+            continue;
+        }
         let Some(chunk) = terrain_map.chunks.get(&ev.0) else {
             return;
         };
-        let mesh = create_terrain_mesh(&chunk.height_map);
+        let lod = lod_settings.lod(camera, ev.0, &terrain_map.config);
+        let mesh = create_terrain_mesh(
+            &chunk.height_map,
+            Some(&chunk.biome_map),
+            &terrain_map.config,
+            lod,
+            lod_settings.skirt_depth,
+        );
         let mesh_handle = meshes.add(mesh);
-        let material = materials.add(StandardMaterial {
-            base_color: Color::srgb_u8(125, 125, 125),
-            ..default()
-        });
-        let x_offset = ev.0 .0 as f32 * CHUNK_SIZE;
-        let z_offset = ev.0 .1 as f32 * CHUNK_SIZE;
-
-        cmd.spawn((
-            MaterialMeshBundle {
-                mesh: mesh_handle,
-                material,
-                transform: Transform::from_xyz(x_offset, 0.0, z_offset),
-                ..default()
-            },
-            WireframeColor {
-                color: BLACK.into(),
-            },
-        ));
+        let x_offset = ev.0 .0 as f32 * terrain_map.config.chunk_size;
+        let z_offset = ev.0 .1 as f32 * terrain_map.config.chunk_size;
+
+        let entity = cmd
+            .spawn((
+                MaterialMeshBundle {
+                    mesh: mesh_handle,
+                    material: materials.terrain.clone(),
+                    transform: Transform::from_xyz(x_offset, 0.0, z_offset),
+                    ..default()
+                },
+                WireframeColor {
+                    color: BLACK.into(),
+                },
+                ChunkMesh { id: ev.0, lod },
+            ))
+            .id();
+        if let Some(water) = water::create_water_mesh(&chunk.height_map, &terrain_map.config) {
+            let water = cmd
+                .spawn((
+                    MaterialMeshBundle {
+                        mesh: meshes.add(water),
+                        material: materials.water.clone(),
+                        ..default()
+                    },
+                    WaterMesh,
+                ))
+                .id();
+            cmd.entity(entity).add_child(water);
+        }
+        // meshing a chunk again replaces its old mesh
+        if let Some(old) = terrain_map.entities.insert(ev.0, entity) {
+            cmd.entity(old).despawn_recursive();
+        }
     }
 }
 
-fn create_terrain_mesh(map: &HeightMap) -> Mesh {
-    let size = CHUNK_SIZE;
-    let width = HEIGHT_MAP_SIZE;
-    let depth = HEIGHT_MAP_SIZE;
-    let height = 2.0;
+/// Meshes a chunk with every `2^lod`th sample. The border samples are always kept
+/// and a skirt hangs down from the border, which hides the cracks to neighbours
+/// with a different level of detail. Without biomes the mesh has no vertex colors.
+fn create_terrain_mesh(
+    map: &HeightMap,
+    biomes: Option<&BiomeMap>,
+    config: &TerrainConfig,
+    lod: usize,
+    skirt_depth: f32,
+) -> Mesh {
+    let size = config.chunk_size;
+    let height = config.height_scale;
+    let step = 1 << lod;
+    let mut samples: Vec<usize> = (0..map.size()).step_by(step).collect();
+    if samples.last() != Some(&(map.size() - 1)) {
+        samples.push(map.size() - 1);
+    }
+    let width = samples.len();
+    let depth = samples.len();
 
     let mut vertex_positions: Vec<[f32; 3]> = vec![];
     let mut normals: Vec<[f32; 3]> = vec![];
+    let mut colors: Vec<[f32; 4]> = vec![];
     let mut indices: Vec<u32> = vec![];
-    for x in 0..width {
-        for z in 0..depth {
+    for &x in samples.iter() {
+        for &z in samples.iter() {
             let position = [
-                size / (width as f32 - 1.0) * x as f32 - size / 2.0,
+                size / (map.size() as f32 - 1.0) * x as f32 - size / 2.0,
                 height * map.get(x, z),
-                size / (depth as f32 - 1.0) * z as f32 - size / 2.0,
+                size / (map.size() as f32 - 1.0) * z as f32 - size / 2.0,
             ];
             vertex_positions.push(position);
-        }
-    }
-    for x in 0..width {
-        for z in 0..depth {
-            // let normal = [0.0, 1.0, 0.0];
             let normal = map.get_normal(x, z);
-            let normal = [normal.x, normal.y, normal.z];
-            normals.push(normal);
+            normals.push([normal.x, normal.y, normal.z]);
+            if let Some(biomes) = biomes {
+                let [r, g, b, a] = biomes.biome(x, z).color();
+                colors.push(Color::srgba_u8(r, g, b, a).to_linear().to_f32_array());
+            }
         }
     }
 
     for x in 0..(width - 1) {
         for z in 0..(depth - 1) {
-            let index = (z * depth + x) as u32;
+            let index = (x * depth + z) as u32;
             //first triangle
             indices.push(index);
             indices.push(index + 1);
@@ -237,17 +569,57 @@ fn create_terrain_mesh(map: &HeightMap) -> Mesh {
             indices.push(index + depth as u32);
         }
     }
-    Mesh::new(
+
+    // the border vertices in order and the direction the skirt faces
+    let borders = [
+        ((0..depth).collect::<Vec<_>>(), Vec3::NEG_X),
+        (
+            (0..depth).map(|z| (width - 1) * depth + z).collect(),
+            Vec3::X,
+        ),
+        ((0..width).map(|x| x * depth).collect(), Vec3::NEG_Z),
+        ((0..width).map(|x| x * depth + depth - 1).collect(), Vec3::Z),
+    ];
+    for (border, outward) in borders {
+        let start = vertex_positions.len() as u32;
+        for &index in border.iter() {
+            let [x, y, z] = vertex_positions[index];
+            vertex_positions.push([x, y - skirt_depth, z]);
+            normals.push(normals[index]);
+            if biomes.is_some() {
+                colors.push(colors[index]);
+            }
+        }
+        for i in 0..border.len() - 1 {
+            let (top_a, top_b) = (border[i] as u32, border[i + 1] as u32);
+            let (bottom_a, bottom_b) = (start + i as u32, start + i as u32 + 1);
+            // the vertices run along the border, flip the winding if the skirt
+            // would face into the chunk
+            let along = Vec3::from(vertex_positions[top_b as usize])
+                - Vec3::from(vertex_positions[top_a as usize]);
+            if along.cross(Vec3::NEG_Y).dot(outward) > 0.0 {
+                indices.extend([top_a, top_b, bottom_a, top_b, bottom_b, bottom_a]);
+            } else {
+                indices.extend([top_a, bottom_a, top_b, top_b, bottom_a, bottom_b]);
+            }
+        }
+    }
+
+    let mut mesh = Mesh::new(
         PrimitiveTopology::TriangleList,
         RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
     )
     .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertex_positions)
     .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
-    .with_inserted_indices(Indices::U32(indices))
+    .with_inserted_indices(Indices::U32(indices));
+    if biomes.is_some() {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    }
+    mesh
 }
 
-fn create_plane(size: f32, sub_div: u32) -> Mesh {
-    let perlin = PerlinNoise::new();
+#[allow(dead_code)]
+fn create_plane<N: NoiseSource + ?Sized>(size: f32, sub_div: u32, noise: &N) -> Mesh {
     let width = 2 + sub_div;
     let depth = 2 + sub_div;
 
@@ -256,7 +628,7 @@ fn create_plane(size: f32, sub_div: u32) -> Mesh {
     let mut indices: Vec<u32> = vec![];
     for x in 0..width {
         for z in 0..depth {
-            let y = perlin.fractal_brownian_motion(x as f32, z as f32);
+            let y = noise.sample2d(x as f32, z as f32);
             let position = [
                 size / width as f32 * x as f32 - size / 2.0,
                 y,
@@ -296,15 +668,16 @@ fn create_plane(size: f32, sub_div: u32) -> Mesh {
     .with_inserted_indices(Indices::U32(indices))
 }
 
-fn noise_vec(width: u32, height: u32) -> Vec<u8> {
+#[allow(dead_code)]
+fn noise_vec<N: NoiseSource + ?Sized>(width: u32, height: u32, noise: &N) -> Vec<u8> {
     let mut pixel_data = Vec::with_capacity((width * height * 4) as usize);
-    let perlin = PerlinNoise::new();
+    let (min, max) = noise.range();
 
     for y in 0..height {
         for x in 0..width {
             let xi = x as f32;
             let yi = y as f32;
-            let p = (perlin.fractal_brownian_motion(xi, yi) + 1.0) / 2.0;
+            let p = (noise.sample2d(xi, yi) - min) / (max - min);
             let val = 255.0 * p;
             let a = 255;
 
@@ -345,8 +718,9 @@ pub fn setup_image(
     mut cmd: Commands,
     mut images: ResMut<Assets<Image>>,
     mut egui_user_textures: ResMut<EguiUserTextures>,
+    config: Res<TerrainConfig>,
 ) {
-    let data = PixelData::empty(HEIGHT_MAP_SIZE as u32, HEIGHT_MAP_SIZE as u32);
+    let data = PixelData::empty(config.samples as u32, config.samples as u32);
     let image = data.to_image();
     let handle = images.add(image);
     egui_user_textures.add_image(handle.clone());
@@ -362,3 +736,74 @@ pub fn debug_ui_system(mut ctx: EguiContexts, my_image: Res<HeighMapImage>) {
         ));
     });
 }
+
+/// 3x3 chunks around the origin generated from the noise
+#[cfg(test)]
+pub(super) fn test_terrain() -> TerrainMap {
+    let config = TerrainConfig {
+        samples: 17,
+        ..default()
+    };
+    let noise = NoiseNode::perlin(0).fbm(FractalSettings::default());
+    let mut chunks = HashMap::new();
+    for x in -1..=1 {
+        for z in -1..=1 {
+            let height_map = HeightMap::new((x, z), &noise, &config);
+            let biome_map = BiomeMap::new((x, z), &height_map, &noise, &config, 0, &[]);
+            chunks.insert(
+                (x, z),
+                Chunk {
+                    height_map,
+                    biome_map,
+                },
+            );
+        }
+    }
+    TerrainMap {
+        chunks,
+        pending: HashMap::new(),
+        cached: HashMap::new(),
+        cache_order: VecDeque::new(),
+        entities: HashMap::new(),
+        changed: HashSet::new(),
+        biomes_changed: HashSet::new(),
+        config,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        TerrainConfig::default().validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 2 samples")]
+    fn single_sample_is_rejected() {
+        TerrainConfig {
+            samples: 1,
+            ..Default::default()
+        }
+        .validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk size must be positive")]
+    fn empty_chunk_is_rejected() {
+        TerrainConfig {
+            chunk_size: 0.0,
+            ..Default::default()
+        }
+        .validate();
+    }
+
+    #[test]
+    fn chunk_at_rounds_to_the_centered_chunk() {
+        let config = TerrainConfig::default();
+        assert_eq!(config.chunk_at(Vec3::new(2.4, 0.0, -2.4)), (0, 0));
+        assert_eq!(config.chunk_at(Vec3::new(2.6, 0.0, -2.6)), (1, -1));
+    }
+}
@@ -0,0 +1,197 @@
+use bevy::prelude::*;
+
+use super::{ChunkGenerationBudget, ChunkId, SpawnTerrainMeshEvent, TerrainMap};
+
+/// the sides of a chunk, as the step towards the neighbour across it
+const SIDES: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+impl TerrainMap {
+    /// Recomputes the normals of a chunk from its heights. Border samples read the
+    /// one sample apron from the neighbouring chunks, so the lighting is continuous at
+    /// the seams. Without a neighbour the border falls back to one sided differences.
+    pub fn recompute_normals(&mut self, id: ChunkId) {
+        let Some(chunk) = self.chunks.get(&id) else {
+            return;
+        };
+        let size = chunk.height_map.size();
+        let mut normals = Vec::with_capacity(size * size);
+        for x in 0..size {
+            for z in 0..size {
+                normals.push(self.sample_normal(id, x, z, false));
+            }
+        }
+        if let Some(chunk) = self.chunks.get_mut(&id) {
+            chunk.height_map.normal = normals;
+        }
+    }
+
+    /// Recomputes the normals on the `side` of a chunk from the heights of the
+    /// neighbour across it, returns `true` if any of them changed.
+    fn recompute_side_normals(&mut self, id: ChunkId, side: (isize, isize)) -> bool {
+        let Some(chunk) = self.chunks.get(&id) else {
+            return false;
+        };
+        let size = chunk.height_map.size();
+        let last = size - 1;
+        let normals: Vec<_> = (0..size)
+            .map(|i| match side {
+                (-1, _) => (0, i),
+                (1, _) => (last, i),
+                (_, -1) => (i, 0),
+                _ => (i, last),
+            })
+            .map(|(x, z)| (x, z, self.sample_normal(id, x, z, true)))
+            .collect();
+        let Some(chunk) = self.chunks.get_mut(&id) else {
+            return false;
+        };
+        let mut changed = false;
+        for (x, z, normal) in normals {
+            let index = chunk.height_map.get_index(x, z);
+            changed |= chunk.height_map.normal[index] != normal;
+            chunk.height_map.normal[index] = normal;
+        }
+        changed
+    }
+
+    /// The normal of a sample from the central differences of its heights, reading
+    /// across the border into the neighbouring chunks. Where a neighbour is missing
+    /// the slope along that axis is kept from the current normal with `keep_slope`,
+    /// or else falls back to a one sided difference.
+    fn sample_normal(&self, id: ChunkId, x: usize, z: usize, keep_slope: bool) -> Vec3 {
+        let map = &self.chunks[&id].height_map;
+        let size = map.size() as isize;
+        let cells = size - 1;
+        let height = |x: isize, z: isize| {
+            if (0..size).contains(&x) && (0..size).contains(&z) {
+                Some(map.get(x as usize, z as usize))
+            } else {
+                self.height_sample(id.0 * cells + x, id.1 * cells + z)
+            }
+        };
+        let scale = self.config.height_scale / self.config.sample_spacing();
+        let current = map.get_normal(x, z);
+        let difference = |before: Option<f32>, center: f32, after: Option<f32>, slope: f32| {
+            match (before, after) {
+                (Some(before), Some(after)) => (after - before) / 2.0,
+                // the normal is (-dx * scale, 1, -dz * scale) normalized
+                _ if keep_slope => -slope / current.y / scale,
+                (Some(before), None) => center - before,
+                (None, Some(after)) => after - center,
+                (None, None) => 0.0,
+            }
+        };
+        let center = map.get(x, z);
+        let (x, z) = (x as isize, z as isize);
+        let dx = difference(height(x - 1, z), center, height(x + 1, z), current.x);
+        let dz = difference(height(x, z - 1), center, height(x, z + 1), current.z);
+        Vec3::new(-dx * scale, 1.0, -dz * scale).normalize()
+    }
+
+    /// Recomputes the normals after the heights of a chunk changed, including the
+    /// neighbours whose border normals read this chunk. Returns the chunks that
+    /// have to be meshed again, they are also queued for [`remesh_changed_chunks`].
+    pub fn heights_changed(&mut self, id: ChunkId) -> Vec<ChunkId> {
+        let mut changed = Vec::new();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                let neighbour = (id.0 + x, id.1 + z);
+                if self.chunks.contains_key(&neighbour) {
+                    self.recompute_normals(neighbour);
+                    changed.push(neighbour);
+                }
+            }
+        }
+        self.changed.extend(changed.iter().copied());
+        changed
+    }
+
+    /// Recomputes the normals on the seams of a freshly generated chunk from the
+    /// heights across the border instead of the noise. The rest keeps the normals
+    /// of [`HeightMap::patch`]. Neighbours whose normals changed are queued for
+    /// [`remesh_changed_chunks`].
+    pub fn chunk_generated(&mut self, id: ChunkId) {
+        for (x, z) in SIDES {
+            let neighbour = (id.0 + x, id.1 + z);
+            if !self.chunks.contains_key(&neighbour) {
+                continue;
+            }
+            self.recompute_side_normals(id, (x, z));
+            if self.recompute_side_normals(neighbour, (-x, -z)) {
+                self.changed.insert(neighbour);
+            }
+        }
+    }
+}
+
+/// Sends a [`SpawnTerrainMeshEvent`] for every loaded chunk whose heights or normals
+/// changed since it was meshed, at most [`ChunkGenerationBudget::chunks_per_frame`]
+/// per frame. Cached chunks are meshed when they are loaded again.
+pub fn remesh_changed_chunks(
+    mut map: ResMut<TerrainMap>,
+    budget: Res<ChunkGenerationBudget>,
+    mut event: EventWriter<SpawnTerrainMeshEvent>,
+) {
+    let map = &mut *map;
+    map.changed.retain(|id| map.chunks.contains_key(id));
+    let remesh: Vec<ChunkId> = map
+        .changed
+        .iter()
+        .copied()
+        .take(budget.chunks_per_frame)
+        .collect();
+    for id in remesh {
+        map.changed.remove(&id);
+        event.send(SpawnTerrainMeshEvent(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::terrain_gen::test_terrain;
+
+    #[test]
+    fn generated_chunks_only_recompute_their_seams() {
+        let mut map = test_terrain();
+        let last = map.config.samples - 1;
+        // a neighbour whose heights no longer match the noise, e.g. after erosion,
+        // apart from the samples it shares with the other chunks
+        let neighbour = &mut map.chunks.get_mut(&(1, 0)).unwrap().height_map;
+        for x in 1..=last {
+            for z in 1..last {
+                neighbour.set(x, z, 1.0);
+            }
+        }
+        let before = map.chunks[&(0, 0)].height_map.normal.clone();
+        map.chunk_generated((0, 0));
+        let after = map.chunks[&(0, 0)].height_map.normal.clone();
+        for x in 0..=last {
+            for z in 0..=last {
+                let index = map.chunks[&(0, 0)].height_map.get_index(x, z);
+                if x == last && (1..last).contains(&z) {
+                    assert_ne!(after[index], before[index]);
+                } else {
+                    assert!(after[index].abs_diff_eq(before[index], 1e-6));
+                }
+            }
+        }
+        assert!(map.changed.contains(&(1, 0)));
+        // with every neighbour loaded the seams match a full recompute
+        map.recompute_normals((0, 0));
+        let full = &map.chunks[&(0, 0)].height_map.normal;
+        for (after, full) in after.iter().zip(full) {
+            assert!(after.abs_diff_eq(*full, 1e-6));
+        }
+    }
+
+    #[test]
+    fn missing_neighbours_keep_the_generated_slope() {
+        let mut map = test_terrain();
+        map.chunks.remove(&(-1, 0));
+        let before = map.chunks[&(0, 0)].height_map.normal.clone();
+        map.chunk_generated((0, 0));
+        for (after, before) in map.chunks[&(0, 0)].height_map.normal.iter().zip(&before) {
+            assert!(after.abs_diff_eq(*before, 1e-5));
+        }
+    }
+}
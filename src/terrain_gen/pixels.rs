@@ -1,13 +1,10 @@
-use bevy::{
-    log,
-    render::{
-        render_asset::RenderAssetUsages,
-        render_resource::{Extent3d, TextureDimension, TextureFormat},
-        texture::Image,
-    },
+use bevy::render::{
+    render_asset::RenderAssetUsages,
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+    texture::Image,
 };
 
-use super::{HeightMap, HEIGHT_MAP_SIZE};
+use super::{BiomeMap, ColorGradient, HeightMap};
 
 pub type Pixel = [u8; 4];
 
@@ -29,8 +26,42 @@ impl PixelData {
             .collect();
         Self {
             pixels,
-            width: HEIGHT_MAP_SIZE as u32,
-            height: HEIGHT_MAP_SIZE as u32,
+            width: map.size() as u32,
+            height: map.size() as u32,
+        }
+    }
+
+    /// the steepness of every sample from flat at 0 to vertical at 255, for
+    /// [`ColorGradient::slope`]
+    pub fn from_slope(map: &HeightMap) -> Self {
+        let pixels = map
+            .normal
+            .iter()
+            .map(|normal| {
+                let v =
+                    (normal.y.clamp(0.0, 1.0).acos() / std::f32::consts::FRAC_PI_2 * 255.0) as u8;
+                [v, v, v, 255]
+            })
+            .collect();
+        Self {
+            pixels,
+            width: map.size() as u32,
+            height: map.size() as u32,
+        }
+    }
+
+    pub fn from_biome_map(map: &BiomeMap) -> Self {
+        // same order as the height data
+        let mut pixels = vec![];
+        for x in 0..map.size() {
+            for y in 0..map.size() {
+                pixels.push(map.biome(x, y).color());
+            }
+        }
+        Self {
+            pixels,
+            width: map.size() as u32,
+            height: map.size() as u32,
         }
     }
 
@@ -70,37 +101,10 @@ impl PixelData {
         }
     }
 
-    pub fn apply_gradient(&mut self) {
-        let gradient = vec![
-            (0.1, [0, 0, 120, 255]),
-            (0.3, [0, 50, 200, 255]),
-            (0.4, [0, 200, 200, 255]),
-            (0.45, [150, 200, 50, 255]),
-            (0.5, [20, 200, 50, 255]),
-            (0.7, [100, 100, 100, 255]),
-            (0.9, [150, 200, 200, 255]),
-        ];
-        'out: for pixel in self.pixels.iter_mut() {
-            let t = pixel[0] as f32 / 255.0;
-            if t < gradient.first().unwrap().0 {
-                *pixel = gradient.first().unwrap().1;
-                continue;
-            }
-
-            if t > gradient.last().unwrap().0 {
-                *pixel = gradient.last().unwrap().1;
-                continue;
-            }
-
-            for i in 1..gradient.len() {
-                if t < gradient[i].0 {
-                    let ratio = (t - gradient[i - 1].0) / (gradient[i].0 - gradient[i - 1].0);
-                    *pixel = lerp_pixel(gradient[i - 1].1, gradient[i].1, ratio);
-                    continue 'out;
-                }
-            }
-
-            *pixel = [255, 0, 0, 255];
+    /// colors the pixels by their red channel
+    pub fn apply_gradient(&mut self, gradient: &ColorGradient) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel = gradient.sample(pixel[0] as f32 / 255.0);
         }
     }
 
@@ -119,11 +123,3 @@ impl PixelData {
         )
     }
 }
-
-fn lerp_pixel(start: Pixel, end: Pixel, t: f32) -> Pixel {
-    let r = (start[0] as f32 + t * (end[0] as f32 - start[0] as f32)) as u8;
-    let g = (start[1] as f32 + t * (end[1] as f32 - start[1] as f32)) as u8;
-    let b = (start[2] as f32 + t * (end[2] as f32 - start[2] as f32)) as u8;
-    let a = (start[3] as f32 + t * (end[3] as f32 - start[3] as f32)) as u8;
-    [r, g, b, a]
-}
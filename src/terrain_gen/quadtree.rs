@@ -0,0 +1,410 @@
+//! Quadtree terrain for maps too large for uniform chunks. Every node covers a square
+//! of the world with one height map patch of [`TerrainConfig::samples`] samples, so
+//! deeper nodes have finer detail. Nodes split and merge by their screen space error.
+//!
+//! Add the [`TerrainPlugin`](super::TerrainPlugin) for the seed and config, insert a
+//! [`TerrainQuadTree`] and run [`update_terrain_quadtree`] instead of chunk loaders.
+
+use std::sync::Arc;
+
+use bevy::{
+    color::palettes::css::BLACK,
+    pbr::wireframe::WireframeColor,
+    prelude::*,
+    tasks::{block_on, AsyncComputeTaskPool, Task},
+};
+
+use super::{create_terrain_mesh, terrain_noise, HeightMap, TerrainConfig, TerrainMap};
+use crate::util::noise::{FractalSettings, NoiseNode, WarpSettings};
+
+pub struct QuadTreeSettings {
+    /// the deepest level, where a node is as large as a chunk
+    pub max_depth: u32,
+    /// nodes whose sample spacing covers more pixels on screen are split
+    pub max_screen_error: f32,
+    /// how many patches may start generating per frame
+    pub patches_per_frame: usize,
+    /// how far the skirts hang below a node, relative to its size
+    pub skirt_ratio: f32,
+}
+
+impl Default for QuadTreeSettings {
+    fn default() -> Self {
+        Self {
+            max_depth: 8,
+            max_screen_error: 4.0,
+            patches_per_frame: 8,
+            skirt_ratio: 0.1,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct TerrainQuadTree {
+    pub settings: QuadTreeSettings,
+    root: QuadNode,
+    material: Option<Handle<StandardMaterial>>,
+    /// the terrain noise and the settings it was built from
+    noise: Option<(FractalSettings, Option<WarpSettings>, Arc<NoiseNode>)>,
+}
+
+impl TerrainQuadTree {
+    /// A tree whose leaves at `settings.max_depth` line up with the chunks of the
+    /// [`TerrainMap`], the root is centered on chunk (0, 0).
+    pub fn new(settings: QuadTreeSettings, config: &TerrainConfig) -> Self {
+        let chunks = (1u32 << settings.max_depth) as f32;
+        let size = chunks * config.chunk_size;
+        let min = Vec2::splat(-(chunks / 2.0) * config.chunk_size - config.chunk_size / 2.0);
+        Self {
+            settings,
+            root: QuadNode::new(min, size, 0),
+            material: None,
+            noise: None,
+        }
+    }
+
+    /// the number of nodes that are currently shown
+    pub fn leaf_count(&self) -> usize {
+        self.root.leaf_count()
+    }
+}
+
+struct QuadNode {
+    /// the world x-z corner with the lowest coordinates
+    min: Vec2,
+    size: f32,
+    depth: u32,
+    /// kept after a split, so merging back is cheap
+    patch: Option<HeightMap>,
+    /// the patch being generated on the [`AsyncComputeTaskPool`]
+    task: Option<Task<HeightMap>>,
+    entity: Option<Entity>,
+    children: Option<Box<[QuadNode; 4]>>,
+}
+
+/// everything a node needs to update itself
+struct UpdateContext<'a, 'w, 's> {
+    cmd: &'a mut Commands<'w, 's>,
+    meshes: &'a mut Assets<Mesh>,
+    material: Handle<StandardMaterial>,
+    noise: &'a Arc<NoiseNode>,
+    config: &'a TerrainConfig,
+    settings: &'a QuadTreeSettings,
+    camera: &'a ViewInfo,
+    budget: usize,
+}
+
+/// what the screen space error of a node depends on
+struct ViewInfo {
+    position: Vec3,
+    viewport_height: f32,
+    /// `None` for an orthographic projection
+    fov: Option<f32>,
+    /// visible world units of an orthographic projection
+    area_height: f32,
+}
+
+impl ViewInfo {
+    fn pixels_per_unit(&self, distance: f32) -> f32 {
+        match self.fov {
+            Some(fov) => self.viewport_height / (2.0 * distance.max(0.001) * (fov / 2.0).tan()),
+            None => self.viewport_height / self.area_height,
+        }
+    }
+}
+
+impl QuadNode {
+    fn new(min: Vec2, size: f32, depth: u32) -> Self {
+        Self {
+            min,
+            size,
+            depth,
+            patch: None,
+            task: None,
+            entity: None,
+            children: None,
+        }
+    }
+
+    fn leaf_count(&self) -> usize {
+        match &self.children {
+            Some(children) => children.iter().map(QuadNode::leaf_count).sum(),
+            None => 1,
+        }
+    }
+
+    /// pixels covered by the sample spacing of this node when seen from the camera
+    fn screen_error(&self, camera: &ViewInfo, config: &TerrainConfig) -> f32 {
+        let position = camera.position;
+        let max = self.min + self.size;
+        let dx = (self.min.x - position.x).max(position.x - max.x).max(0.0);
+        let dz = (self.min.y - position.z).max(position.z - max.y).max(0.0);
+        let distance = Vec3::new(dx, position.y, dz).length();
+        let spacing = self.size / (config.samples - 1) as f32;
+        spacing * camera.pixels_per_unit(distance)
+    }
+
+    /// `true` if the node should show its children, `false` if it should be merged
+    fn wants_split(
+        &self,
+        camera: &ViewInfo,
+        config: &TerrainConfig,
+        settings: &QuadTreeSettings,
+    ) -> bool {
+        self.depth < settings.max_depth
+            && self.screen_error(camera, config) > settings.max_screen_error
+    }
+
+    fn update(&mut self, ctx: &mut UpdateContext) {
+        let split = self.wants_split(ctx.camera, ctx.config, ctx.settings);
+        match (split, &mut self.children) {
+            (true, Some(children)) if self.entity.is_none() => {
+                for child in children.iter_mut() {
+                    child.update(ctx);
+                }
+            }
+            (true, children) => {
+                let half = self.size / 2.0;
+                let (min, depth) = (self.min, self.depth + 1);
+                let children = children.get_or_insert_with(|| {
+                    Box::new(
+                        [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+                            .map(|(x, z)| QuadNode::new(min + Vec2::new(x, z) * half, half, depth)),
+                    )
+                });
+                // this node stays visible until the patches of all four children are done
+                let mut ready = true;
+                for child in children.iter_mut() {
+                    ready &= child.prepare(ctx);
+                }
+                if ready {
+                    for child in children.iter_mut() {
+                        child.show(ctx);
+                    }
+                    self.hide(ctx);
+                } else {
+                    self.show(ctx);
+                }
+            }
+            (false, Some(_)) => {
+                if self.show(ctx) {
+                    if let Some(mut children) = self.children.take() {
+                        for child in children.iter_mut() {
+                            child.hide_all(ctx);
+                        }
+                    }
+                }
+            }
+            (false, None) => {
+                self.show(ctx);
+            }
+        }
+    }
+
+    /// starts generating the patch of this node, `true` once it is done
+    fn prepare(&mut self, ctx: &mut UpdateContext) -> bool {
+        if self.patch.is_some() {
+            return true;
+        }
+        if self.task.is_none() {
+            if ctx.budget == 0 {
+                return false;
+            }
+            ctx.budget -= 1;
+            let spacing = ctx.config.sample_spacing();
+            // chunk (0, 0) is centered on the origin
+            let origin = (self.min + ctx.config.chunk_size / 2.0) / spacing;
+            let scale = self.size / (ctx.config.samples - 1) as f32 / spacing;
+            let noise = ctx.noise.clone();
+            let config = ctx.config.clone();
+            self.task =
+                Some(AsyncComputeTaskPool::get().spawn(async move {
+                    HeightMap::patch(origin, scale, noise.as_ref(), &config)
+                }));
+        }
+        match self.task.take() {
+            Some(task) if task.is_finished() => {
+                self.patch = Some(block_on(task));
+                true
+            }
+            task => {
+                self.task = task;
+                false
+            }
+        }
+    }
+
+    /// spawns the mesh of this node, `false` while its patch is generated
+    fn show(&mut self, ctx: &mut UpdateContext) -> bool {
+        if self.entity.is_some() {
+            return true;
+        }
+        if !self.prepare(ctx) {
+            return false;
+        }
+        let Some(patch) = &self.patch else {
+            return false;
+        };
+        let config = TerrainConfig {
+            chunk_size: self.size,
+            ..ctx.config.clone()
+        };
+        let mesh = create_terrain_mesh(
+            patch,
+            None,
+            &config,
+            0,
+            self.size * ctx.settings.skirt_ratio,
+        );
+        let center = self.min + self.size / 2.0;
+        let entity = ctx
+            .cmd
+            .spawn((
+                MaterialMeshBundle {
+                    mesh: ctx.meshes.add(mesh),
+                    material: ctx.material.clone(),
+                    transform: Transform::from_xyz(center.x, 0.0, center.y),
+                    ..default()
+                },
+                WireframeColor {
+                    color: BLACK.into(),
+                },
+            ))
+            .id();
+        self.entity = Some(entity);
+        true
+    }
+
+    fn hide(&mut self, ctx: &mut UpdateContext) {
+        if let Some(entity) = self.entity.take() {
+            ctx.cmd.entity(entity).despawn_recursive();
+        }
+    }
+
+    fn hide_all(&mut self, ctx: &mut UpdateContext) {
+        self.hide(ctx);
+        if let Some(children) = &mut self.children {
+            for child in children.iter_mut() {
+                child.hide_all(ctx);
+            }
+        }
+    }
+}
+
+/// Splits and merges the quadtree nodes for the active 3d camera.
+#[allow(clippy::too_many_arguments)]
+pub fn update_terrain_quadtree(
+    mut cmd: Commands,
+    mut tree: ResMut<TerrainQuadTree>,
+    map: Res<TerrainMap>,
+    settings: Res<FractalSettings>,
+    warp: Option<Res<WarpSettings>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    q_camera: Query<(&Camera, &Projection, &GlobalTransform), With<Camera3d>>,
+) {
+    let Some((camera, projection, transform)) =
+        q_camera.iter().find(|(camera, _, _)| camera.is_active)
+    else {
+        return;
+    };
+    let view = ViewInfo {
+        position: transform.translation(),
+        viewport_height: camera.logical_viewport_size().map_or(720.0, |size| size.y),
+        fov: match projection {
+            Projection::Perspective(perspective) => Some(perspective.fov),
+            Projection::Orthographic(_) => None,
+        },
+        area_height: match projection {
+            Projection::Orthographic(orthographic) => orthographic.area.height(),
+            Projection::Perspective(_) => 1.0,
+        },
+    };
+    let material = tree
+        .material
+        .get_or_insert_with(|| {
+            materials.add(StandardMaterial {
+                base_color: Color::srgb_u8(125, 125, 125),
+                ..default()
+            })
+        })
+        .clone();
+    let tree = tree.as_mut();
+    let warp = warp.as_deref();
+    let noise = match &tree.noise {
+        Some((built, built_warp, noise)) if *built == *settings && built_warp.as_ref() == warp => {
+            noise.clone()
+        }
+        _ => {
+            let noise = Arc::new(terrain_noise(&map, &settings, warp));
+            tree.noise = Some((settings.clone(), warp.cloned(), noise.clone()));
+            noise
+        }
+    };
+    let mut ctx = UpdateContext {
+        cmd: &mut cmd,
+        meshes: &mut meshes,
+        material,
+        noise: &noise,
+        config: &map.config,
+        settings: &tree.settings,
+        camera: &view,
+        budget: tree.settings.patches_per_frame,
+    };
+    tree.root.update(&mut ctx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perspective(position: Vec3) -> ViewInfo {
+        ViewInfo {
+            position,
+            viewport_height: 720.0,
+            fov: Some(std::f32::consts::FRAC_PI_4),
+            area_height: 1.0,
+        }
+    }
+
+    #[test]
+    fn screen_error_falls_with_the_distance() {
+        let config = TerrainConfig::default();
+        let node = QuadNode::new(Vec2::ZERO, 10.0, 0);
+        let above = node.screen_error(&perspective(Vec3::new(5.0, 1.0, 5.0)), &config);
+        let near = node.screen_error(&perspective(Vec3::new(20.0, 1.0, 5.0)), &config);
+        let far = node.screen_error(&perspective(Vec3::new(200.0, 1.0, 5.0)), &config);
+        assert!(above > near && near > far);
+        // the error only depends on the distance to the node, not its center
+        let other_side = node.screen_error(&perspective(Vec3::new(-10.0, 1.0, 5.0)), &config);
+        assert!((near - other_side).abs() < 1e-4);
+        // an orthographic view has the same error everywhere
+        let ortho = |position| ViewInfo {
+            fov: None,
+            area_height: 20.0,
+            ..perspective(position)
+        };
+        let spacing = 10.0 / (config.samples - 1) as f32;
+        for position in [Vec3::ZERO, Vec3::new(500.0, 30.0, 0.0)] {
+            let error = node.screen_error(&ortho(position), &config);
+            assert!((error - spacing * 720.0 / 20.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn nodes_split_near_the_camera_and_merge_far_away() {
+        let config = TerrainConfig::default();
+        let settings = QuadTreeSettings::default();
+        let node = QuadNode::new(Vec2::ZERO, 10.0, 3);
+        assert!(node.wants_split(&perspective(Vec3::new(5.0, 1.0, 5.0)), &config, &settings));
+        assert!(!node.wants_split(&perspective(Vec3::new(500.0, 1.0, 5.0)), &config, &settings));
+        // the deepest nodes never split
+        let leaf = QuadNode::new(Vec2::ZERO, 10.0, settings.max_depth);
+        assert!(!leaf.wants_split(&perspective(Vec3::new(5.0, 1.0, 5.0)), &config, &settings));
+        // a child covers half the size, so it needs to be closer to split again
+        let child = QuadNode::new(Vec2::ZERO, 5.0, 4);
+        let camera = perspective(Vec3::new(25.0, 1.0, 2.5));
+        let error = node.screen_error(&camera, &config);
+        assert!(child.screen_error(&camera, &config) < error);
+    }
+}
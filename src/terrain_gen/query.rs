@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+
+use super::{Chunk, TerrainMap};
+use crate::util::interpolation::catmull_rom;
+
+/// How the height between the samples is interpolated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeightInterpolation {
+    /// linear over the two triangles of a cell, exactly the surface of the mesh
+    #[default]
+    Triangles,
+    Bilinear,
+    /// catmull-rom over the surrounding 4x4 samples, smooth but may overshoot
+    Bicubic,
+}
+
+impl TerrainMap {
+    /// the height of the terrain mesh at the world (x, z) position, `None` if the
+    /// chunk isn't generated
+    pub fn height_at(&self, position: Vec2) -> Option<f32> {
+        self.height_at_with(position, HeightInterpolation::Triangles)
+    }
+
+    pub fn height_at_with(
+        &self,
+        position: Vec2,
+        interpolation: HeightInterpolation,
+    ) -> Option<f32> {
+        let (cell, t) = self.cell_at(position);
+        let height = |x: isize, z: isize| self.height_sample(cell.0 + x, cell.1 + z);
+        let height = match interpolation {
+            HeightInterpolation::Triangles => {
+                let [w00, w10, w01, w11] = triangle_weights(t);
+                w00 * height(0, 0)?
+                    + w10 * height(1, 0)?
+                    + w01 * height(0, 1)?
+                    + w11 * height(1, 1)?
+            }
+            HeightInterpolation::Bilinear => {
+                let near = height(0, 0)?.lerp(height(1, 0)?, t.x);
+                let far = height(0, 1)?.lerp(height(1, 1)?, t.x);
+                near.lerp(far, t.y)
+            }
+            HeightInterpolation::Bicubic => {
+                let mut rows = [0.0; 4];
+                for (z, row) in (-1..=2).zip(rows.iter_mut()) {
+                    *row = catmull_rom(
+                        [height(-1, z)?, height(0, z)?, height(1, z)?, height(2, z)?],
+                        t.x,
+                    );
+                }
+                catmull_rom(rows, t.y)
+            }
+        };
+        Some(height * self.config.height_scale)
+    }
+
+    /// the shading normal of the terrain mesh at the world (x, z) position
+    pub fn normal_at(&self, position: Vec2) -> Option<Vec3> {
+        let (cell, t) = self.cell_at(position);
+        let normal = |x: isize, z: isize| {
+            let (chunk, x, z) = self.sample(cell.0 + x, cell.1 + z)?;
+            Some(chunk.height_map.get_normal(x, z))
+        };
+        let [w00, w10, w01, w11] = triangle_weights(t);
+        let normal =
+            w00 * normal(0, 0)? + w10 * normal(1, 0)? + w01 * normal(0, 1)? + w11 * normal(1, 1)?;
+        Some(normal.normalize())
+    }
+
+    /// the global sample index of the cell that contains the position and the
+    /// position within the cell
+    fn cell_at(&self, position: Vec2) -> ((isize, isize), Vec2) {
+        let size = self.config.chunk_size;
+        // chunk (0, 0) is centered on the origin
+        let sample = (position + size / 2.0) / self.config.sample_spacing();
+        let cell = sample.floor();
+        ((cell.x as isize, cell.y as isize), sample - cell)
+    }
+
+    /// the chunk and local indices of a global sample index
+    pub(super) fn sample(&self, x: isize, z: isize) -> Option<(&Chunk, usize, usize)> {
+        let cells = self.config.samples as isize - 1;
+        // samples on a chunk border are shared, they can also come from the chunk
+        // on the lower side
+        let candidates = |i: isize| {
+            let (chunk, local) = (i.div_euclid(cells), i.rem_euclid(cells));
+            let shared = (local == 0).then_some((chunk - 1, cells));
+            [Some((chunk, local)), shared].into_iter().flatten()
+        };
+        for (chunk_x, local_x) in candidates(x) {
+            for (chunk_z, local_z) in candidates(z) {
+                if let Some(chunk) = self.chunks.get(&(chunk_x, chunk_z)) {
+                    return Some((chunk, local_x as usize, local_z as usize));
+                }
+            }
+        }
+        None
+    }
+
+    pub(super) fn height_sample(&self, x: isize, z: isize) -> Option<f32> {
+        let (chunk, x, z) = self.sample(x, z)?;
+        Some(chunk.height_map.get(x, z))
+    }
+}
+
+/// weights of the cell corners (0, 0), (1, 0), (0, 1), (1, 1), the cell is split
+/// along the (0, 1) - (1, 0) diagonal like in `create_terrain_mesh`
+fn triangle_weights(t: Vec2) -> [f32; 4] {
+    if t.x + t.y <= 1.0 {
+        [1.0 - t.x - t.y, t.x, t.y, 0.0]
+    } else {
+        [0.0, 1.0 - t.y, 1.0 - t.x, t.x + t.y - 1.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangle_weights_at_the_corners() {
+        assert_eq!(triangle_weights(Vec2::new(0.0, 0.0)), [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(triangle_weights(Vec2::new(1.0, 0.0)), [0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(triangle_weights(Vec2::new(0.0, 1.0)), [0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(triangle_weights(Vec2::new(1.0, 1.0)), [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn triangle_weights_sum_to_one_and_agree_on_the_diagonal() {
+        for i in 0..=10 {
+            for j in 0..=10 {
+                let t = Vec2::new(i as f32, j as f32) / 10.0;
+                let sum: f32 = triangle_weights(t).iter().sum();
+                assert!((sum - 1.0).abs() < 1e-6);
+            }
+            // both triangles give the same weights on the shared diagonal
+            let t = i as f32 / 10.0;
+            let below = triangle_weights(Vec2::new(t, 1.0 - t) - 1e-7);
+            let above = triangle_weights(Vec2::new(t, 1.0 - t) + 1e-7);
+            for (below, above) in below.iter().zip(above) {
+                assert!((below - above).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn triangle_weights_interpolate_planes_exactly() {
+        // a height field that is a plane is reproduced by both triangles
+        let plane = |x: f32, z: f32| 0.3 + 2.0 * x - 0.5 * z;
+        let corners = [
+            plane(0.0, 0.0),
+            plane(1.0, 0.0),
+            plane(0.0, 1.0),
+            plane(1.0, 1.0),
+        ];
+        for t in [
+            Vec2::new(0.2, 0.3),
+            Vec2::new(0.8, 0.7),
+            Vec2::new(0.5, 0.5),
+        ] {
+            let weights = triangle_weights(t);
+            let height: f32 = weights.iter().zip(corners).map(|(w, h)| w * h).sum();
+            assert!((height - plane(t.x, t.y)).abs() < 1e-6);
+        }
+    }
+}
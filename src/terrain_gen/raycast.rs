@@ -0,0 +1,237 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use super::{ChunkId, TerrainMap};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainHit {
+    pub position: Vec3,
+    pub normal: Vec3,
+    /// distance along the ray
+    pub distance: f32,
+    pub chunk: ChunkId,
+    /// sample indices of the hit cell's corner nearest to the chunk origin
+    pub cell: (usize, usize),
+}
+
+/// The terrain under the mouse cursor, updated every frame.
+#[derive(Resource)]
+pub struct TerrainCursor {
+    pub hit: Option<TerrainHit>,
+    /// how far from the camera the terrain is picked
+    pub max_distance: f32,
+}
+
+impl Default for TerrainCursor {
+    fn default() -> Self {
+        Self {
+            hit: None,
+            max_distance: 500.0,
+        }
+    }
+}
+
+impl TerrainMap {
+    /// the first intersection of the ray with the terrain mesh, only generated chunks
+    /// are hit
+    pub fn raycast(&self, ray: Ray3d, max_distance: f32) -> Option<TerrainHit> {
+        let spacing = self.config.sample_spacing();
+        let half_size = self.config.chunk_size / 2.0;
+        let cells = self.config.samples as isize - 1;
+        let direction = *ray.direction;
+        // walk the cells in the x-z plane, see "A Fast Voxel Traversal Algorithm" by
+        // Amanatides and Woo
+        let start = (Vec2::new(ray.origin.x, ray.origin.z) + half_size) / spacing;
+        let mut cell = (start.x.floor() as isize, start.y.floor() as isize);
+        let axis = |position: f32, cell: isize, direction: f32| {
+            if direction > 0.0 {
+                (
+                    1,
+                    ((cell + 1) as f32 - position) * spacing / direction,
+                    spacing / direction,
+                )
+            } else if direction < 0.0 {
+                (
+                    -1,
+                    (cell as f32 - position) * spacing / direction,
+                    -spacing / direction,
+                )
+            } else {
+                (0, f32::INFINITY, f32::INFINITY)
+            }
+        };
+        let (step_x, mut next_x, delta_x) = axis(start.x, cell.0, direction.x);
+        let (step_z, mut next_z, delta_z) = axis(start.y, cell.1, direction.z);
+
+        let mut distance = 0.0;
+        while distance <= max_distance {
+            if let Some(hit) = self.intersect_cell(ray, cell, max_distance) {
+                let chunk = (cell.0.div_euclid(cells), cell.1.div_euclid(cells));
+                return Some(TerrainHit {
+                    normal: self
+                        .normal_at(Vec2::new(hit.position.x, hit.position.z))
+                        .unwrap_or(hit.normal),
+                    chunk,
+                    cell: (
+                        cell.0.rem_euclid(cells) as usize,
+                        cell.1.rem_euclid(cells) as usize,
+                    ),
+                    ..hit
+                });
+            }
+            if next_x < next_z {
+                distance = next_x;
+                next_x += delta_x;
+                cell.0 += step_x;
+            } else {
+                distance = next_z;
+                next_z += delta_z;
+                cell.1 += step_z;
+            }
+        }
+        None
+    }
+
+    /// the nearest hit of the two triangles of a cell, split like in `create_terrain_mesh`
+    fn intersect_cell(
+        &self,
+        ray: Ray3d,
+        cell: (isize, isize),
+        max_distance: f32,
+    ) -> Option<TerrainHit> {
+        let spacing = self.config.sample_spacing();
+        let half_size = self.config.chunk_size / 2.0;
+        let vertex = |x: isize, z: isize| {
+            let (chunk, local_x, local_z) = self.sample(cell.0 + x, cell.1 + z)?;
+            Some(Vec3::new(
+                (cell.0 + x) as f32 * spacing - half_size,
+                chunk.height_map.get(local_x, local_z) * self.config.height_scale,
+                (cell.1 + z) as f32 * spacing - half_size,
+            ))
+        };
+        let v00 = vertex(0, 0)?;
+        let v01 = vertex(0, 1)?;
+        let v10 = vertex(1, 0)?;
+        let v11 = vertex(1, 1)?;
+        [[v00, v01, v10], [v01, v11, v10]]
+            .into_iter()
+            .filter_map(|triangle| intersect_triangle(ray, triangle))
+            .filter(|hit| hit.distance <= max_distance)
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+}
+
+/// Möller-Trumbore ray triangle intersection, both sides of the triangle are hit
+fn intersect_triangle(ray: Ray3d, [a, b, c]: [Vec3; 3]) -> Option<TerrainHit> {
+    let edge_1 = b - a;
+    let edge_2 = c - a;
+    let p = ray.direction.cross(edge_2);
+    let determinant = edge_1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let offset = ray.origin - a;
+    let u = offset.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = offset.cross(edge_1);
+    let v = ray.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = edge_2.dot(q) * inverse;
+    if distance < 0.0 {
+        return None;
+    }
+    let normal = edge_1.cross(edge_2).normalize();
+    Some(TerrainHit {
+        position: ray.get_point(distance),
+        normal: if normal.y < 0.0 { -normal } else { normal },
+        distance,
+        chunk: (0, 0),
+        cell: (0, 0),
+    })
+}
+
+/// Picks the terrain under the cursor through the active camera with the highest order.
+pub fn update_terrain_cursor(
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    map: Option<Res<TerrainMap>>,
+    mut cursor: ResMut<TerrainCursor>,
+) {
+    cursor.hit = None;
+    let Some(map) = map else {
+        return;
+    };
+    let Some(position) = q_window.get_single().ok().and_then(Window::cursor_position) else {
+        return;
+    };
+    let Some((camera, transform)) = q_camera
+        .iter()
+        .filter(|(camera, _)| camera.is_active)
+        .max_by_key(|(camera, _)| camera.order)
+    else {
+        return;
+    };
+    let Some(ray) = camera.viewport_to_world(transform, position) else {
+        return;
+    };
+    cursor.hit = map.raycast(ray, cursor.max_distance);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: [Vec3; 3] = [
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec3::new(1.0, 1.0, 0.0),
+    ];
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray3d {
+        Ray3d::new(origin, direction)
+    }
+
+    #[test]
+    fn hits_the_triangle_from_above() {
+        let hit = intersect_triangle(ray(Vec3::new(0.25, 3.0, 0.25), Vec3::NEG_Y), TRIANGLE)
+            .expect("the ray points at the triangle");
+        assert!((hit.distance - 2.0).abs() < 1e-6);
+        assert!(hit.position.abs_diff_eq(Vec3::new(0.25, 1.0, 0.25), 1e-6));
+        assert!(hit.normal.abs_diff_eq(Vec3::Y, 1e-6));
+    }
+
+    #[test]
+    fn normal_points_up_from_below() {
+        let hit = intersect_triangle(ray(Vec3::new(0.25, -1.0, 0.25), Vec3::Y), TRIANGLE)
+            .expect("both sides are hit");
+        assert!((hit.distance - 2.0).abs() < 1e-6);
+        assert!(hit.normal.y > 0.0);
+    }
+
+    #[test]
+    fn misses_outside_parallel_and_behind() {
+        // past the (0, 1) - (1, 0) diagonal
+        assert!(
+            intersect_triangle(ray(Vec3::new(0.75, 3.0, 0.75), Vec3::NEG_Y), TRIANGLE).is_none()
+        );
+        // parallel to the plane of the triangle
+        assert!(intersect_triangle(ray(Vec3::new(-1.0, 1.0, 0.25), Vec3::X), TRIANGLE).is_none());
+        // the triangle is behind the origin
+        assert!(intersect_triangle(ray(Vec3::new(0.25, 3.0, 0.25), Vec3::Y), TRIANGLE).is_none());
+    }
+
+    #[test]
+    fn slanted_hit_lies_on_the_ray() {
+        let direction = Vec3::new(0.3, -1.0, 0.2).normalize();
+        let origin = Vec3::new(0.0, 2.0, 0.0);
+        let hit = intersect_triangle(ray(origin, direction), TRIANGLE).expect("hits the triangle");
+        assert!(hit
+            .position
+            .abs_diff_eq(origin + direction * hit.distance, 1e-5));
+        assert!((hit.position.y - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,192 @@
+use std::f32::consts::SQRT_2;
+
+use super::{ChunkId, TerrainMap};
+
+/// The heights of a rectangular block of chunks as one grid, so post-processing
+/// can run across chunk borders. Heights are in height map units, one sample apart.
+#[derive(Clone, Debug)]
+pub struct HeightField {
+    pub width: usize,
+    pub height: usize,
+    /// global sample index of the first sample
+    pub origin: (isize, isize),
+    pub data: Vec<f32>,
+    /// world units between two samples
+    pub sample_spacing: f32,
+    /// scales the heights to world units
+    pub height_scale: f32,
+}
+
+/// the 8 direct and diagonal neighbours of a sample and their distance in samples
+pub(super) const NEIGHBOURS: [(isize, isize, f32); 8] = [
+    (-1, -1, SQRT_2),
+    (0, -1, 1.0),
+    (1, -1, SQRT_2),
+    (-1, 0, 1.0),
+    (1, 0, 1.0),
+    (-1, 1, SQRT_2),
+    (0, 1, 1.0),
+    (1, 1, SQRT_2),
+];
+
+impl HeightField {
+    fn index(&self, x: usize, z: usize) -> usize {
+        x + z * self.width
+    }
+
+    /// the indices of the [`NEIGHBOURS`] inside the field and their distance
+    pub(super) fn neighbours(&self, index: usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let (x, z) = ((index % self.width) as isize, (index / self.width) as isize);
+        NEIGHBOURS.iter().filter_map(move |(dx, dz, distance)| {
+            let (nx, nz) = (x + dx, z + dz);
+            if nx < 0 || nz < 0 || nx >= self.width as isize || nz >= self.height as isize {
+                return None;
+            }
+            Some((nx as usize + nz as usize * self.width, *distance))
+        })
+    }
+
+    pub fn get(&self, x: usize, z: usize) -> f32 {
+        self.data[self.index(x, z)]
+    }
+
+    pub fn set(&mut self, x: usize, z: usize, height: f32) {
+        let index = self.index(x, z);
+        self.data[index] = height;
+    }
+
+    /// fades the changes out towards the border, so the outermost samples keep their
+    /// heights and match the chunks outside of the region
+    fn fade_border(&mut self, original: &HeightField, margin: usize) {
+        for z in 0..self.height {
+            for x in 0..self.width {
+                let distance = x.min(z).min(self.width - 1 - x).min(self.height - 1 - z);
+                if distance >= margin {
+                    continue;
+                }
+                let t = distance as f32 / margin as f32;
+                let before = original.get(x, z);
+                self.set(x, z, before + (self.get(x, z) - before) * t);
+            }
+        }
+    }
+}
+
+/// samples at the region border over which post-processing fades out
+const BORDER_MARGIN: usize = 8;
+
+impl TerrainMap {
+    /// the heights of the chunks from `min` to `max` inclusive, `None` if any of them
+    /// isn't generated
+    pub fn height_field(&self, min: ChunkId, max: ChunkId) -> Option<HeightField> {
+        let cells = self.config.samples as isize - 1;
+        let width = ((max.0 - min.0 + 1) * cells + 1) as usize;
+        let height = ((max.1 - min.1 + 1) * cells + 1) as usize;
+        let origin = (min.0 * cells, min.1 * cells);
+        let mut data = Vec::with_capacity(width * height);
+        for z in 0..height as isize {
+            for x in 0..width as isize {
+                data.push(self.height_sample(origin.0 + x, origin.1 + z)?);
+            }
+        }
+        Some(HeightField {
+            width,
+            height,
+            origin,
+            data,
+            sample_spacing: self.config.sample_spacing(),
+            height_scale: self.config.height_scale,
+        })
+    }
+
+    /// Writes the heights back into every chunk they overlap and recomputes the
+    /// normals. Returns the chunks that have to be meshed again, they are also queued
+    /// for [`remesh_changed_chunks`](super::remesh_changed_chunks) and
+    /// [`rebuild_changed_biomes`](super::rebuild_changed_biomes).
+    pub fn apply_height_field(&mut self, field: &HeightField) -> Vec<ChunkId> {
+        let cells = self.config.samples as isize - 1;
+        let min = (
+            field.origin.0.div_euclid(cells),
+            field.origin.1.div_euclid(cells),
+        );
+        let max = (
+            (field.origin.0 + field.width as isize - 1).div_euclid(cells),
+            (field.origin.1 + field.height as isize - 1).div_euclid(cells),
+        );
+        let mut changed = Vec::new();
+        // one more chunk on each side, they share the border samples
+        for chunk_x in min.0 - 1..=max.0 {
+            for chunk_z in min.1 - 1..=max.1 {
+                let id = (chunk_x, chunk_z);
+                let Some(chunk) = self.chunks.get_mut(&id) else {
+                    continue;
+                };
+                let map = &mut chunk.height_map;
+                let mut touched = false;
+                for x in 0..map.size() {
+                    for z in 0..map.size() {
+                        let field_x = chunk_x * cells + x as isize - field.origin.0;
+                        let field_z = chunk_z * cells + z as isize - field.origin.1;
+                        if !(0..field.width as isize).contains(&field_x)
+                            || !(0..field.height as isize).contains(&field_z)
+                        {
+                            continue;
+                        }
+                        map.set(x, z, field.get(field_x as usize, field_z as usize));
+                        touched = true;
+                    }
+                }
+                if touched {
+                    changed.push(id);
+                }
+            }
+        }
+        // the normals of the neighbours read the changed heights as well
+        let mut remesh = Vec::new();
+        for id in changed {
+            for x in -1..=1 {
+                for z in -1..=1 {
+                    let neighbour = (id.0 + x, id.1 + z);
+                    if self.chunks.contains_key(&neighbour) && !remesh.contains(&neighbour) {
+                        remesh.push(neighbour);
+                    }
+                }
+            }
+        }
+        for id in remesh.iter() {
+            self.recompute_normals(*id);
+        }
+        self.biomes_changed.extend(remesh.iter().copied());
+        self.changed.extend(remesh.iter().copied());
+        remesh
+    }
+
+    /// Runs a post-processing step over the chunks from `min` to `max` inclusive.
+    /// The changes fade out towards the region border, so there are no seams to the
+    /// chunks around it. Returns the chunks that have to be meshed again, `None` if
+    /// a chunk of the region isn't generated.
+    pub fn process_region(
+        &mut self,
+        min: ChunkId,
+        max: ChunkId,
+        process: impl FnOnce(&mut HeightField),
+    ) -> Option<Vec<ChunkId>> {
+        let field = self.processed_region(min, max, process)?;
+        Some(self.apply_height_field(&field))
+    }
+
+    /// the heights of the region after `process`, faded out towards the border like
+    /// [`TerrainMap::process_region`] but not applied yet
+    pub(super) fn processed_region(
+        &self,
+        min: ChunkId,
+        max: ChunkId,
+        process: impl FnOnce(&mut HeightField),
+    ) -> Option<HeightField> {
+        let mut field = self.height_field(min, max)?;
+        let original = field.clone();
+        process(&mut field);
+        field.fade_border(&original, BORDER_MARGIN);
+        Some(field)
+    }
+}
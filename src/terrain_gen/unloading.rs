@@ -0,0 +1,81 @@
+use bevy::{log, prelude::*};
+
+use super::{ChunkId, DespawnTerrainMeshEvent, TerrainMap};
+
+/// When chunks far from the loaders are unloaded and how many of their height maps
+/// are kept around for when the loaders come back.
+#[derive(Resource)]
+pub struct ChunkUnloadSettings {
+    /// extra chunks beyond the load range before a chunk is unloaded, so moving back
+    /// and forth over a chunk border doesn't reload the same chunks
+    pub hysteresis: isize,
+    /// height maps of unloaded chunks kept in memory
+    pub cache_capacity: usize,
+}
+
+impl Default for ChunkUnloadSettings {
+    fn default() -> Self {
+        Self {
+            hysteresis: 1,
+            cache_capacity: 64,
+        }
+    }
+}
+
+impl TerrainMap {
+    /// `true` if the chunk has a height map and isn't only cached
+    pub fn is_loaded(&self, id: ChunkId) -> bool {
+        self.chunks.contains_key(&id)
+    }
+
+    /// moves a loaded chunk into the cache and drops a pending one, returns `true`
+    /// if its mesh should be despawned
+    pub fn unload_chunk(&mut self, id: ChunkId) -> bool {
+        // dropping the task cancels the generation
+        if self.pending.remove(&id).is_some() {
+            return false;
+        }
+        let Some(chunk) = self.chunks.remove(&id) else {
+            return false;
+        };
+        self.cached.insert(id, chunk);
+        self.cache_order.push_back(id);
+        true
+    }
+
+    /// moves a chunk from the cache back into the map, returns `true` if it has to
+    /// be meshed again. Its seams are recomputed against the neighbours loaded since.
+    pub fn restore_cached(&mut self, id: ChunkId) -> bool {
+        let Some(chunk) = self.cached.remove(&id) else {
+            return false;
+        };
+        // at most the cache capacity to search
+        self.cache_order.retain(|cached| *cached != id);
+        self.chunks.insert(id, chunk);
+        self.chunk_generated(id);
+        true
+    }
+
+    /// drops the least recently used cached height maps until at most `capacity` are left
+    pub fn evict_cached(&mut self, capacity: usize) {
+        while self.cached.len() > capacity {
+            let Some(id) = self.cache_order.pop_front() else {
+                break;
+            };
+            self.cached.remove(&id);
+            log::info!("Evicted chunk: {:?}", id);
+        }
+    }
+}
+
+pub fn despawn_terrain_meshes(
+    mut cmd: Commands,
+    mut event: EventReader<DespawnTerrainMeshEvent>,
+    mut map: ResMut<TerrainMap>,
+) {
+    for ev in event.read() {
+        if let Some(entity) = map.entities.remove(&ev.0) {
+            cmd.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+    },
+    utils::hashbrown::HashMap,
+};
+
+use super::{HeightMap, TerrainConfig, TerrainMap};
+
+/// What covers a sample, relative to [`TerrainConfig::sea_level`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Surface {
+    DeepWater,
+    ShallowWater,
+    /// land just above the sea level
+    Shore,
+    Land,
+}
+
+impl Surface {
+    pub fn is_water(self) -> bool {
+        matches!(self, Surface::DeepWater | Surface::ShallowWater)
+    }
+}
+
+/// Marks the water plane of a chunk, a child of the chunk's terrain mesh.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct WaterMesh;
+
+impl TerrainConfig {
+    /// classifies a world height
+    pub fn surface(&self, height: f32) -> Surface {
+        let above_sea = height - self.sea_level;
+        if above_sea < -self.shallow_depth {
+            Surface::DeepWater
+        } else if above_sea < 0.0 {
+            Surface::ShallowWater
+        } else if above_sea < self.shore_height {
+            Surface::Shore
+        } else {
+            Surface::Land
+        }
+    }
+}
+
+impl HeightMap {
+    /// the classification of every sample, indexed like the heights
+    pub fn surfaces(&self, config: &TerrainConfig) -> Vec<Surface> {
+        self.height_data
+            .iter()
+            .map(|height| config.surface(height * config.height_scale))
+            .collect()
+    }
+}
+
+impl TerrainMap {
+    /// the surface at the world (x, z) position, `None` if the chunk isn't generated
+    pub fn surface_at(&self, position: Vec2) -> Option<Surface> {
+        Some(self.config.surface(self.height_at(position)?))
+    }
+
+    /// if the world (x, z) position is below the sea level, chunks that aren't
+    /// generated count as land
+    pub fn is_water(&self, position: Vec2) -> bool {
+        self.surface_at(position).is_some_and(Surface::is_water)
+    }
+}
+
+/// A flat mesh at the sea level over the cells that have a sample below it, `None`
+/// if the chunk is dry. The terrain hides the parts of the cells above the water.
+pub(super) fn create_water_mesh(map: &HeightMap, config: &TerrainConfig) -> Option<Mesh> {
+    let size = map.size();
+    let spacing = config.sample_spacing();
+    let surfaces = map.surfaces(config);
+    let wet = |x: usize, z: usize| surfaces[map.get_index(x, z)].is_water();
+
+    let mut vertex_positions: Vec<[f32; 3]> = vec![];
+    let mut vertices = HashMap::new();
+    let mut vertex = |x: usize, z: usize| {
+        *vertices.entry((x, z)).or_insert_with(|| {
+            vertex_positions.push([
+                spacing * x as f32 - config.chunk_size / 2.0,
+                config.sea_level,
+                spacing * z as f32 - config.chunk_size / 2.0,
+            ]);
+            vertex_positions.len() as u32 - 1
+        })
+    };
+    let mut indices: Vec<u32> = vec![];
+    for x in 0..size - 1 {
+        for z in 0..size - 1 {
+            if !(wet(x, z) || wet(x + 1, z) || wet(x, z + 1) || wet(x + 1, z + 1)) {
+                continue;
+            }
+            // same triangles as the terrain mesh
+            let (a, b, c, d) = (
+                vertex(x, z),
+                vertex(x, z + 1),
+                vertex(x + 1, z),
+                vertex(x + 1, z + 1),
+            );
+            indices.extend([a, b, c, b, d, c]);
+        }
+    }
+    if indices.is_empty() {
+        return None;
+    }
+
+    let normals = vec![[0.0, 1.0, 0.0]; vertex_positions.len()];
+    Some(
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertex_positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_indices(Indices::U32(indices)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_gen::test_terrain;
+
+    #[test]
+    fn surface_bands_around_the_sea_level() {
+        let config = TerrainConfig {
+            sea_level: 1.0,
+            shallow_depth: 0.5,
+            shore_height: 0.25,
+            ..default()
+        };
+        assert_eq!(config.surface(0.0), Surface::DeepWater);
+        assert_eq!(config.surface(0.5 - 1e-3), Surface::DeepWater);
+        assert_eq!(config.surface(0.5), Surface::ShallowWater);
+        assert_eq!(config.surface(1.0 - 1e-3), Surface::ShallowWater);
+        assert_eq!(config.surface(1.0), Surface::Shore);
+        assert_eq!(config.surface(1.25 - 1e-3), Surface::Shore);
+        assert_eq!(config.surface(1.25), Surface::Land);
+        assert!(config.surface(0.9).is_water());
+        assert!(!config.surface(1.0).is_water());
+    }
+
+    #[test]
+    fn is_water_below_the_sea_level() {
+        let mut map = test_terrain();
+        let size = map.config.samples;
+        let height_scale = map.config.height_scale;
+        let sea_level = map.config.sea_level;
+        let flatten = |map: &mut TerrainMap, world_height: f32| {
+            let height_map = &mut map.chunks.get_mut(&(0, 0)).unwrap().height_map;
+            for x in 0..size {
+                for z in 0..size {
+                    height_map.set(x, z, world_height / height_scale);
+                }
+            }
+        };
+        flatten(&mut map, sea_level - 0.1);
+        assert!(map.is_water(Vec2::ZERO));
+        let surfaces = map.chunks[&(0, 0)].height_map.surfaces(&map.config);
+        assert!(surfaces.into_iter().all(Surface::is_water));
+        flatten(&mut map, sea_level + 0.05);
+        assert!(!map.is_water(Vec2::ZERO));
+        assert_eq!(map.surface_at(Vec2::ZERO), Some(Surface::Shore));
+        // chunks that aren't generated count as land
+        assert!(!map.is_water(Vec2::splat(100.0)));
+    }
+}
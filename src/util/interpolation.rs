@@ -0,0 +1,22 @@
+/// Catmull-Rom spline between `p1` and `p2` at `t` in [0, 1], `p0` and `p3` set the
+/// tangents. It passes through the points but may overshoot them.
+pub fn catmull_rom([p0, p1, p2, p3]: [f32; 4], t: f32) -> f32 {
+    let a = -0.5 * p0 + 1.5 * p1 - 1.5 * p2 + 0.5 * p3;
+    let b = p0 - 2.5 * p1 + 2.0 * p2 - 0.5 * p3;
+    let c = -0.5 * p0 + 0.5 * p2;
+    ((a * t + b) * t + c) * t + p1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catmull_rom_passes_through_the_inner_points() {
+        let points = [1.0, -2.0, 3.0, 0.5];
+        assert_eq!(catmull_rom(points, 0.0), -2.0);
+        assert!((catmull_rom(points, 1.0) - 3.0).abs() < 1e-6);
+        // linear data stays linear
+        assert!((catmull_rom([0.0, 1.0, 2.0, 3.0], 0.25) - 1.25).abs() < 1e-6);
+    }
+}
@@ -1,2 +1,3 @@
+pub mod interpolation;
 pub mod noise;
-
+pub mod rng;
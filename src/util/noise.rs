@@ -1,105 +0,0 @@
-// Hash lookup table as defined by Ken Perlin. This is a randomly arranged array of all numbers from 0-255 inclusive.
-const PERMUTATION: [u8; 256] = [
-    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
-    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
-    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
-    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
-    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
-    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
-    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
-    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
-    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
-    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
-    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
-    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
-    128, 195, 78, 66, 215, 61, 156, 180,
-];
-
-pub struct PerlinNoise {
-    p: [u8; 512],
-}
-
-impl PerlinNoise {
-    pub fn new() -> Self {
-        let mut p = [0; 512];
-        for i in 0..512 {
-            p[i] = PERMUTATION[i % 256];
-        }
-        Self { p }
-    }
-
-    pub fn noise2d_with_freq(&self, x: f32, y: f32) -> f32 {
-        let frequency = 0.05;
-        self.noise2d(x * frequency, y * frequency)
-    }
-
-    pub fn fractal_brownian_motion(&self, x: f32, y: f32) -> f32 {
-        let octave_count = 8;
-        let mut res = 0.0;
-        let mut amplitude = 1.0;
-        let mut frequency = 0.005;
-        for _ in 0..octave_count {
-            let n = amplitude * self.noise2d(x * frequency, y * frequency);
-            res += n;
-            amplitude *= 0.5;
-            frequency *= 2.0;
-        }
-        res
-    }
-
-    /// a noise with range [-1.0, 1.0]
-    pub fn noise2d(&self, x: f32, y: f32) -> f32 {
-        let xi = index_wrap(x.floor() as isize, 256);
-        let yi = index_wrap(y.floor() as isize, 256);
-
-        let tx = x - x.floor();
-        let ty = y - y.floor();
-        // get hash function value at the 4 surrounding lattice points
-        let top_right = (tx - 1.0, ty - 1.0);
-        let top_left = (tx, ty - 1.0);
-        let bottom_right = (tx - 1.0, ty);
-        let bottom_left = (tx, ty);
-
-        let val_top_right = self.p[self.p[xi + 1] as usize + yi + 1];
-        let val_top_left = self.p[self.p[xi] as usize + yi + 1];
-        let val_bottom_right = self.p[self.p[xi + 1] as usize + yi];
-        let val_bottom_left = self.p[self.p[xi] as usize + yi];
-
-        let dot_top_right = dot(top_right, get_vec(val_top_right));
-        let dot_top_left = dot(top_left, get_vec(val_top_left));
-        let dot_bottom_right = dot(bottom_right, get_vec(val_bottom_right));
-        let dot_bottom_left = dot(bottom_left, get_vec(val_bottom_left));
-        //smoothstep
-        let smooth_tx = smoothstep(tx);
-        let smooth_ty = smoothstep(ty);
-        // lerp
-        let s = lerp(dot_bottom_left, dot_top_left, smooth_ty);
-        let n = lerp(dot_bottom_right, dot_top_right, smooth_ty);
-        lerp(s, n, smooth_tx)
-    }
-}
-
-fn index_wrap(i: isize, size: usize) -> usize {
-    ((i % size as isize) + size as isize) as usize % size
-}
-
-fn dot(v0: (f32, f32), v1: (f32, f32)) -> f32 {
-    v0.0 * v1.0 + v0.1 * v1.1
-}
-
-pub fn smoothstep(t: f32) -> f32 {
-    ((6.0 * t - 15.0) * t + 10.0) * t * t * t
-}
-
-fn lerp(start: f32, end: f32, t: f32) -> f32 {
-    start + t * (end - start)
-}
-
-fn get_vec(value: u8) -> (f32, f32) {
-    match value & 3 {
-        0 => (1.0, 1.0),
-        1 => (-1.0, 1.0),
-        2 => (-1.0, -1.0),
-        _ => (1.0, -1.0),
-    }
-}
@@ -0,0 +1,191 @@
+//! Grid evaluation of perlin noise, several samples at once in fixed size lanes.
+//! Every lane does the same operations in the same order as the scalar path, so the
+//! results are bit identical to [`PerlinNoise::noise2d`].
+
+use bevy::math::Vec2;
+
+use super::{
+    dot, fractal::octaves, get_vec, index_wrap, lerp, smoothstep, FractalSettings, PerlinNoise,
+};
+
+const LANES: usize = 8;
+type Lanes = [f32; LANES];
+
+impl PerlinNoise {
+    /// fills `out` row by row with [`noise2d`](Self::noise2d) at
+    /// `origin + step * (column, row)`
+    pub fn fill_grid(
+        &self,
+        origin: Vec2,
+        step: Vec2,
+        width: usize,
+        height: usize,
+        out: &mut [f32],
+    ) {
+        fill_lanes(origin, step, width, height, out, |x, y| {
+            self.noise2d_lanes(x, y)
+        });
+    }
+
+    /// [`fill_grid`](Self::fill_grid) with
+    /// [`fractal_brownian_motion`](Self::fractal_brownian_motion)
+    pub fn fill_grid_fbm(
+        &self,
+        origin: Vec2,
+        step: Vec2,
+        width: usize,
+        height: usize,
+        settings: &FractalSettings,
+        out: &mut [f32],
+    ) {
+        fill_lanes(origin, step, width, height, out, |x, y| {
+            // the octaves of fbm_octaves, side by side
+            let mut res = [0.0; LANES];
+            for (amplitude, frequency) in octaves(settings) {
+                let n = self.noise2d_lanes(&x.map(|x| x * frequency), &y.map(|y| y * frequency));
+                for (res, n) in res.iter_mut().zip(n) {
+                    *res += amplitude * n;
+                }
+            }
+            res.map(|res| res + settings.offset)
+        });
+    }
+
+    fn noise2d_lanes(&self, x: &Lanes, y: &Lanes) -> Lanes {
+        let tx: Lanes = std::array::from_fn(|i| x[i] - x[i].floor());
+        let ty: Lanes = std::array::from_fn(|i| y[i] - y[i].floor());
+        // the lattice lookups are the only part that can't run side by side
+        let corners: [[u8; 4]; LANES] = std::array::from_fn(|i| {
+            let x0 = index_wrap(x[i].floor() as isize, 256);
+            let y0 = index_wrap(y[i].floor() as isize, 256);
+            [
+                self.p.hash2(x0, y0),
+                self.p.hash2(x0 + 1, y0),
+                self.p.hash2(x0, y0 + 1),
+                self.p.hash2(x0 + 1, y0 + 1),
+            ]
+        });
+        let smooth_tx = tx.map(smoothstep);
+        let smooth_ty = ty.map(smoothstep);
+        std::array::from_fn(|i| {
+            let (tx, ty) = (tx[i], ty[i]);
+            let [bottom_left, bottom_right, top_left, top_right] = corners[i];
+            let dot_top_right = dot((tx - 1.0, ty - 1.0), get_vec(top_right));
+            let dot_top_left = dot((tx, ty - 1.0), get_vec(top_left));
+            let dot_bottom_right = dot((tx - 1.0, ty), get_vec(bottom_right));
+            let dot_bottom_left = dot((tx, ty), get_vec(bottom_left));
+            let s = lerp(dot_bottom_left, dot_top_left, smooth_ty[i]);
+            let n = lerp(dot_bottom_right, dot_top_right, smooth_ty[i]);
+            lerp(s, n, smooth_tx[i])
+        })
+    }
+}
+
+/// walks the grid in chunks of [`LANES`] samples, the last chunk of a row is padded
+fn fill_lanes(
+    origin: Vec2,
+    step: Vec2,
+    width: usize,
+    height: usize,
+    out: &mut [f32],
+    eval: impl Fn(&Lanes, &Lanes) -> Lanes,
+) {
+    assert!(
+        out.len() >= width * height,
+        "grid of {width}x{height} doesn't fit into {} samples",
+        out.len()
+    );
+    for row in 0..height {
+        let y = [origin.y + row as f32 * step.y; LANES];
+        for start in (0..width).step_by(LANES) {
+            let x = std::array::from_fn(|i| origin.x + (start + i) as f32 * step.x);
+            let values = eval(&x, &y);
+            let len = LANES.min(width - start);
+            let offset = row * width + start;
+            out[offset..offset + len].copy_from_slice(&values[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise::{NoiseNode, NoiseSource};
+
+    /// (origin, step, width, height), widths that aren't multiples of the lanes
+    const GRIDS: [(Vec2, Vec2, usize, usize); 4] = [
+        (Vec2::new(-37.25, -12.5), Vec2::new(0.37, 0.61), 13, 5),
+        (Vec2::new(-300.0, 41.0), Vec2::new(1.0, 1.0), 17, 3),
+        (Vec2::new(0.0, -0.75), Vec2::new(2.5, 0.1), 1, 9),
+        (Vec2::new(-1.5, -1.5), Vec2::new(0.25, 0.25), 64, 2),
+    ];
+
+    fn assert_grid(
+        fill: impl Fn(Vec2, Vec2, usize, usize, &mut [f32]),
+        scalar: impl Fn(f32, f32) -> f32,
+    ) {
+        for (origin, step, width, height) in GRIDS {
+            let mut out = vec![0.0; width * height];
+            fill(origin, step, width, height, &mut out);
+            for row in 0..height {
+                for column in 0..width {
+                    let x = origin.x + column as f32 * step.x;
+                    let y = origin.y + row as f32 * step.y;
+                    let (batch, scalar) = (out[row * width + column], scalar(x, y));
+                    assert_eq!(
+                        batch.to_bits(),
+                        scalar.to_bits(),
+                        "({x}, {y}): {batch} != {scalar}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fill_grid_is_bit_identical() {
+        let noise = PerlinNoise::with_seed(11);
+        assert_grid(
+            |origin, step, width, height, out| noise.fill_grid(origin, step, width, height, out),
+            |x, y| noise.noise2d(x, y),
+        );
+    }
+
+    #[test]
+    fn fill_grid_fbm_is_bit_identical() {
+        let noise = PerlinNoise::with_seed(11);
+        let settings = FractalSettings {
+            frequency: 0.07,
+            offset: 0.25,
+            ..Default::default()
+        };
+        assert_grid(
+            |origin, step, width, height, out| {
+                noise.fill_grid_fbm(origin, step, width, height, &settings, out)
+            },
+            |x, y| noise.fractal_brownian_motion(x, y, &settings),
+        );
+    }
+
+    #[test]
+    fn node_fill_grid_is_bit_identical() {
+        let settings = FractalSettings::default();
+        let nodes = [
+            // batched
+            NoiseNode::perlin(3)
+                .fbm(settings.clone())
+                .scale_bias(0.5, 0.1)
+                .clamp(-0.2, 0.4),
+            NoiseNode::perlin(3).min(NoiseNode::perlin(4)),
+            // sampled one at a time
+            NoiseNode::simplex(3).fbm(settings.clone()),
+            NoiseNode::perlin(3).ridged(settings),
+        ];
+        for node in nodes {
+            assert_grid(
+                |origin, step, width, height, out| node.fill_grid(origin, step, width, height, out),
+                |x, y| node.sample2d(x, y),
+            );
+        }
+    }
+}
@@ -0,0 +1,482 @@
+use bevy::{math::Vec2, prelude::Resource};
+use serde::{Deserialize, Serialize};
+
+use super::{periodic::periodic_frequency, NoiseSource, Period, PeriodicNoise};
+
+/// Parameters of the fractal noise used for terrain generation. The same settings
+/// and seed always produce the same terrain.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FractalSettings {
+    pub octaves: u32,
+    /// frequency of the first octave
+    pub frequency: f32,
+    /// amplitude of the first octave
+    pub amplitude: f32,
+    /// frequency multiplier between octaves
+    pub lacunarity: f32,
+    /// amplitude multiplier between octaves, also called persistence
+    pub gain: f32,
+    /// constant added to the result
+    pub offset: f32,
+}
+
+impl Default for FractalSettings {
+    fn default() -> Self {
+        Self {
+            octaves: 8,
+            frequency: 0.005,
+            amplitude: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+            offset: 0.0,
+        }
+    }
+}
+
+/// Fractal brownian motion over any noise source.
+#[derive(Clone)]
+pub struct Fbm<N> {
+    pub source: N,
+    pub settings: FractalSettings,
+}
+
+impl<N: NoiseSource> Fbm<N> {
+    pub fn new(source: N, settings: FractalSettings) -> Self {
+        Self { source, settings }
+    }
+}
+
+impl<N: NoiseSource> NoiseSource for Fbm<N> {
+    fn sample2d(&self, x: f32, y: f32) -> f32 {
+        fractal_brownian_motion(&self.source, x, y, &self.settings)
+    }
+
+    fn sample2d_with_derivative(&self, x: f32, y: f32) -> (f32, Vec2) {
+        fractal_brownian_motion_with_derivative(&self.source, x, y, &self.settings)
+    }
+
+    fn sample3d(&self, x: f32, y: f32, z: f32) -> Option<f32> {
+        FractalKind::Fbm.sample3d(&self.source, x, y, z, &self.settings)
+    }
+
+    fn range(&self) -> (f32, f32) {
+        fractal_range(self.source.range(), &self.settings)
+    }
+}
+
+pub fn fractal_brownian_motion<N: NoiseSource + ?Sized>(
+    source: &N,
+    x: f32,
+    y: f32,
+    settings: &FractalSettings,
+) -> f32 {
+    fbm_octaves(settings, |frequency| {
+        source.sample2d(x * frequency, y * frequency)
+    })
+}
+
+/// [`fractal_brownian_motion`] and its derivative, the sum of the scaled octave derivatives.
+pub fn fractal_brownian_motion_with_derivative<N: NoiseSource + ?Sized>(
+    source: &N,
+    x: f32,
+    y: f32,
+    settings: &FractalSettings,
+) -> (f32, Vec2) {
+    let mut res = 0.0;
+    let mut derivative = Vec2::ZERO;
+    let mut amplitude = settings.amplitude;
+    let mut frequency = settings.frequency;
+    for _ in 0..settings.octaves {
+        let (n, d) = source.sample2d_with_derivative(x * frequency, y * frequency);
+        res += amplitude * n;
+        derivative += amplitude * frequency * d;
+        amplitude *= settings.gain;
+        frequency *= settings.lacunarity;
+    }
+    (res + settings.offset, derivative)
+}
+
+/// Derivative damped fbm as described by Inigo Quilez. Octaves on steep slopes are
+/// damped, which gives an eroded look with smooth valleys and sharp ridges.
+pub fn eroded_fbm<N: NoiseSource + ?Sized>(
+    source: &N,
+    x: f32,
+    y: f32,
+    settings: &FractalSettings,
+) -> f32 {
+    eroded_octaves(settings, |frequency| {
+        source.sample2d_with_derivative(x * frequency, y * frequency)
+    })
+}
+
+/// Ridged multifractal, `1 - |n|` per octave. Each octave is weighted by the previous
+/// one, so detail concentrates on the sharp ridges. Expects a source in [-1.0, 1.0].
+pub fn ridged_multifractal<N: NoiseSource + ?Sized>(
+    source: &N,
+    x: f32,
+    y: f32,
+    settings: &FractalSettings,
+) -> f32 {
+    ridged_octaves(settings, |frequency| {
+        source.sample2d(x * frequency, y * frequency)
+    })
+}
+
+/// Billow noise, `|n|` per octave. Gives rounded hills with creased valleys.
+/// Expects a source in [-1.0, 1.0].
+pub fn billow<N: NoiseSource + ?Sized>(
+    source: &N,
+    x: f32,
+    y: f32,
+    settings: &FractalSettings,
+) -> f32 {
+    billow_octaves(settings, |frequency| {
+        source.sample2d(x * frequency, y * frequency)
+    })
+}
+
+/// Hybrid multifractal. Low areas damp the higher octaves, so valleys stay smooth
+/// while peaks get rough. Expects a source in [-1.0, 1.0].
+pub fn hybrid_multifractal<N: NoiseSource + ?Sized>(
+    source: &N,
+    x: f32,
+    y: f32,
+    settings: &FractalSettings,
+) -> f32 {
+    hybrid_octaves(settings, |frequency| {
+        source.sample2d(x * frequency, y * frequency)
+    })
+}
+
+// The generators below get the noise value of an octave from its frequency, so the
+// same code serves the plain and the periodic variants.
+
+/// amplitude and frequency of every octave
+pub(super) fn octaves(settings: &FractalSettings) -> impl Iterator<Item = (f32, f32)> {
+    let (gain, lacunarity) = (settings.gain, settings.lacunarity);
+    std::iter::successors(
+        Some((settings.amplitude, settings.frequency)),
+        move |&(a, f)| Some((a * gain, f * lacunarity)),
+    )
+    .take(settings.octaves as usize)
+}
+
+pub(super) fn fbm_octaves(settings: &FractalSettings, octave: impl Fn(f32) -> f32) -> f32 {
+    let mut res = 0.0;
+    for (amplitude, frequency) in octaves(settings) {
+        res += amplitude * octave(frequency);
+    }
+    res + settings.offset
+}
+
+fn eroded_octaves(settings: &FractalSettings, octave: impl Fn(f32) -> (f32, Vec2)) -> f32 {
+    let mut res = 0.0;
+    let mut slope = Vec2::ZERO;
+    let mut amplitude = settings.amplitude;
+    let mut frequency = settings.frequency;
+    for _ in 0..settings.octaves {
+        let (n, d) = octave(frequency);
+        slope += d;
+        res += amplitude * n / (1.0 + slope.length_squared());
+        amplitude *= settings.gain;
+        frequency *= settings.lacunarity;
+    }
+    res + settings.offset
+}
+
+fn ridged_octaves(settings: &FractalSettings, octave: impl Fn(f32) -> f32) -> f32 {
+    let mut res = 0.0;
+    let mut amplitude = settings.amplitude;
+    let mut frequency = settings.frequency;
+    let mut weight = 1.0;
+    for _ in 0..settings.octaves {
+        let mut signal = 1.0 - octave(frequency).abs();
+        // sharpen the ridges
+        signal *= signal;
+        signal *= weight;
+        weight = (signal * 2.0).clamp(0.0, 1.0);
+        res += signal * amplitude;
+        amplitude *= settings.gain;
+        frequency *= settings.lacunarity;
+    }
+    // map from [0, amplitude_sum] to the same range as fbm
+    2.0 * res - amplitude_sum(settings) + settings.offset
+}
+
+fn billow_octaves(settings: &FractalSettings, octave: impl Fn(f32) -> f32) -> f32 {
+    let mut res = 0.0;
+    let mut amplitude = settings.amplitude;
+    let mut frequency = settings.frequency;
+    for _ in 0..settings.octaves {
+        let signal = 2.0 * octave(frequency).abs() - 1.0;
+        res += signal * amplitude;
+        amplitude *= settings.gain;
+        frequency *= settings.lacunarity;
+    }
+    res + settings.offset
+}
+
+fn hybrid_octaves(settings: &FractalSettings, octave: impl Fn(f32) -> f32) -> f32 {
+    let mut res = 0.0;
+    let mut amplitude = settings.amplitude;
+    let mut frequency = settings.frequency;
+    let mut weight = 1.0;
+    for _ in 0..settings.octaves {
+        let signal = (octave(frequency) + 1.0) * 0.5;
+        res += weight * signal * amplitude;
+        weight = (weight * signal * 2.0).min(1.0);
+        amplitude *= settings.gain;
+        frequency *= settings.lacunarity;
+    }
+    // map from [0, amplitude_sum] to the same range as fbm
+    2.0 * res - amplitude_sum(settings) + settings.offset
+}
+
+/// The fractal generators, all share the parameters of [`FractalSettings`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FractalKind {
+    #[default]
+    Fbm,
+    Ridged,
+    Billow,
+    Hybrid,
+    Eroded,
+}
+
+impl FractalKind {
+    pub fn sample<N: NoiseSource + ?Sized>(
+        &self,
+        source: &N,
+        x: f32,
+        y: f32,
+        settings: &FractalSettings,
+    ) -> f32 {
+        match self {
+            FractalKind::Fbm => fractal_brownian_motion(source, x, y, settings),
+            FractalKind::Ridged => ridged_multifractal(source, x, y, settings),
+            FractalKind::Billow => billow(source, x, y, settings),
+            FractalKind::Hybrid => hybrid_multifractal(source, x, y, settings),
+            FractalKind::Eroded => eroded_fbm(source, x, y, settings),
+        }
+    }
+
+    /// the generator over the 3d variant of the source, `None` if the source has
+    /// none or the generator needs a derivative
+    pub fn sample3d<N: NoiseSource + ?Sized>(
+        &self,
+        source: &N,
+        x: f32,
+        y: f32,
+        z: f32,
+        settings: &FractalSettings,
+    ) -> Option<f32> {
+        source.sample3d(x, y, z)?;
+        let octave = |frequency: f32| {
+            source
+                .sample3d(x * frequency, y * frequency, z * frequency)
+                .unwrap_or(0.0)
+        };
+        match self {
+            FractalKind::Fbm => Some(fbm_octaves(settings, octave)),
+            FractalKind::Ridged => Some(ridged_octaves(settings, octave)),
+            FractalKind::Billow => Some(billow_octaves(settings, octave)),
+            FractalKind::Hybrid => Some(hybrid_octaves(settings, octave)),
+            FractalKind::Eroded => None,
+        }
+    }
+
+    /// the generator over a source that repeats after `period` sample units
+    pub fn sample_periodic<N: PeriodicNoise + ?Sized>(
+        &self,
+        source: &N,
+        x: f32,
+        y: f32,
+        period: Period,
+        settings: &FractalSettings,
+    ) -> f32 {
+        let octave = |frequency: f32| {
+            let (fx, px) = periodic_frequency(frequency, period.x);
+            let (fy, py) = periodic_frequency(frequency, period.y);
+            source.sample2d_periodic(x * fx, y * fy, Period::new(px, py))
+        };
+        match self {
+            FractalKind::Fbm => fbm_octaves(settings, octave),
+            FractalKind::Ridged => ridged_octaves(settings, octave),
+            FractalKind::Billow => billow_octaves(settings, octave),
+            FractalKind::Hybrid => hybrid_octaves(settings, octave),
+            FractalKind::Eroded => eroded_octaves(settings, |frequency| {
+                let (fx, px) = periodic_frequency(frequency, period.x);
+                let (fy, py) = periodic_frequency(frequency, period.y);
+                let lattice_period = Period::new(px, py);
+                let (ox, oy) = (x * fx, y * fy);
+                let d = 0.01;
+                let dx = source.sample2d_periodic(ox + d, oy, lattice_period)
+                    - source.sample2d_periodic(ox - d, oy, lattice_period);
+                let dy = source.sample2d_periodic(ox, oy + d, lattice_period)
+                    - source.sample2d_periodic(ox, oy - d, lattice_period);
+                (
+                    source.sample2d_periodic(ox, oy, lattice_period),
+                    Vec2::new(dx, dy) / (2.0 * d),
+                )
+            }),
+        }
+    }
+
+    /// the range of the generator for a source with the given range
+    pub fn range(&self, source_range: (f32, f32), settings: &FractalSettings) -> (f32, f32) {
+        match self {
+            FractalKind::Fbm => fractal_range(source_range, settings),
+            _ => fractal_range((-1.0, 1.0), settings),
+        }
+    }
+}
+
+fn fractal_range((min, max): (f32, f32), settings: &FractalSettings) -> (f32, f32) {
+    let amplitude_sum = amplitude_sum(settings);
+    (
+        min * amplitude_sum + settings.offset,
+        max * amplitude_sum + settings.offset,
+    )
+}
+
+fn amplitude_sum(settings: &FractalSettings) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = settings.amplitude;
+    for _ in 0..settings.octaves {
+        sum += amplitude.abs();
+        amplitude *= settings.gain;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise::{dense_grid, NoiseNode, PerlinNoise};
+
+    const GENERATORS: [FractalKind; 3] = [
+        FractalKind::Ridged,
+        FractalKind::Billow,
+        FractalKind::Hybrid,
+    ];
+
+    fn settings() -> FractalSettings {
+        FractalSettings {
+            octaves: 5,
+            frequency: 0.3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn same_settings_same_output() {
+        let noise = PerlinNoise::with_seed(5);
+        let settings = FractalSettings::default();
+        for i in 0..100 {
+            let (x, y) = (i as f32 * 7.3 - 300.0, i as f32 * -2.9);
+            let a = fractal_brownian_motion(&noise, x, y, &settings);
+            let b = fractal_brownian_motion(&noise, x, y, &settings.clone());
+            assert_eq!(a.to_bits(), b.to_bits());
+        }
+    }
+
+    #[test]
+    fn single_octave_is_the_scaled_source() {
+        let noise = PerlinNoise::with_seed(5);
+        let settings = FractalSettings {
+            octaves: 1,
+            frequency: 0.1,
+            amplitude: 2.0,
+            offset: 0.5,
+            ..Default::default()
+        };
+        let (x, y) = (12.3, -45.6);
+        let expected = 2.0 * noise.noise2d(x * 0.1, y * 0.1) + 0.5;
+        assert_eq!(fractal_brownian_motion(&noise, x, y, &settings), expected);
+    }
+
+    #[test]
+    fn generators_are_deterministic() {
+        let (a, b) = (PerlinNoise::with_seed(5), PerlinNoise::with_seed(5));
+        let other = PerlinNoise::with_seed(6);
+        let settings = settings();
+        for kind in GENERATORS {
+            let sample =
+                |noise: &PerlinNoise, (x, y): (f32, f32)| kind.sample(noise, x, y, &settings);
+            assert!(dense_grid().all(|p| sample(&a, p).to_bits() == sample(&b, p).to_bits()));
+            assert!(
+                dense_grid().any(|p| sample(&a, p) != sample(&other, p)),
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn generators_stay_in_range() {
+        let noise = PerlinNoise::with_seed(5);
+        let settings = settings();
+        for kind in GENERATORS {
+            let (min, max) = kind.range(noise.range(), &settings);
+            for (x, y) in dense_grid() {
+                let value = kind.sample(&noise, x, y, &settings);
+                assert!(
+                    (min..=max).contains(&value),
+                    "{kind:?} at ({x}, {y}) is {value}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn derivative_value_matches_sample() {
+        let noise = PerlinNoise::with_seed(5);
+        let settings = FractalSettings::default();
+        let (x, y) = (123.4, 56.7);
+        let (value, _) = fractal_brownian_motion_with_derivative(&noise, x, y, &settings);
+        assert!((value - fractal_brownian_motion(&noise, x, y, &settings)).abs() < 1e-6);
+    }
+
+    /// the analytic derivative against central differences with step `h`
+    fn assert_derivative<N: NoiseSource>(noise: &N, h: f32) {
+        for i in 0..200 {
+            let (x, y) = (i as f32 * 0.377 - 31.0, i as f32 * -0.213 + 7.0);
+            let (_, derivative) = noise.sample2d_with_derivative(x, y);
+            let dx = (noise.sample2d(x + h, y) - noise.sample2d(x - h, y)) / (2.0 * h);
+            let dy = (noise.sample2d(x, y + h) - noise.sample2d(x, y - h)) / (2.0 * h);
+            let tolerance = 0.01 * derivative.length().max(1.0);
+            assert!(
+                (derivative - Vec2::new(dx, dy)).length() < tolerance,
+                "({x}, {y}): analytic {derivative}, numeric ({dx}, {dy})"
+            );
+        }
+    }
+
+    #[test]
+    fn perlin_derivative_matches_finite_differences() {
+        assert_derivative(&PerlinNoise::with_seed(5), 1e-3);
+    }
+
+    #[test]
+    fn fbm_derivative_matches_finite_differences() {
+        let settings = FractalSettings {
+            octaves: 5,
+            frequency: 0.2,
+            amplitude: 2.0,
+            ..Default::default()
+        };
+        assert_derivative(&Fbm::new(PerlinNoise::with_seed(5), settings), 1e-3);
+    }
+
+    #[test]
+    fn node_derivative_matches_finite_differences() {
+        let settings = FractalSettings {
+            octaves: 4,
+            frequency: 0.3,
+            ..Default::default()
+        };
+        let node = NoiseNode::perlin(5).fbm(settings).scale_bias(0.5, 0.1) * NoiseNode::perlin(7)
+            + NoiseNode::constant(0.2);
+        assert_derivative(&node, 1e-3);
+    }
+}
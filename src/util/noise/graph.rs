@@ -0,0 +1,710 @@
+use std::{
+    ops::{Add, Mul},
+    sync::Arc,
+};
+
+use bevy::math::Vec2;
+use serde::{Deserialize, Deserializer};
+
+use super::{
+    fill_grid_scalar, finite_difference, fractal_brownian_motion_with_derivative, lerp,
+    warp_position, FractalKind, FractalSettings, NoiseSource, PerlinNoise, SimplexNoise,
+    ValueNoise, WorleyNoise,
+};
+use crate::util::interpolation::catmull_rom;
+
+/// A node of a composable noise graph. The root node samples the whole graph.
+///
+/// Graphs can be built in code with the builder methods or deserialized, e.g. from RON:
+/// `ScaleBias(source: Fractal(source: Perlin(42), settings: (octaves: 6)), scale: 0.5, bias: 0.1)`
+#[derive(Clone, Deserialize)]
+pub enum NoiseNode {
+    Constant(f32),
+    Perlin(PerlinNoise),
+    Simplex(SimplexNoise),
+    Value(ValueNoise),
+    Worley(WorleyNoise),
+    /// any noise source built in code, cannot be deserialized
+    #[serde(skip)]
+    Source(Arc<dyn NoiseSource + Send + Sync>),
+    Fractal {
+        source: Box<NoiseNode>,
+        #[serde(default)]
+        kind: FractalKind,
+        #[serde(default)]
+        settings: FractalSettings,
+    },
+    Add(Box<NoiseNode>, Box<NoiseNode>),
+    Multiply(Box<NoiseNode>, Box<NoiseNode>),
+    Min(Box<NoiseNode>, Box<NoiseNode>),
+    Max(Box<NoiseNode>, Box<NoiseNode>),
+    Clamp {
+        source: Box<NoiseNode>,
+        min: f32,
+        max: f32,
+    },
+    ScaleBias {
+        source: Box<NoiseNode>,
+        scale: f32,
+        bias: f32,
+    },
+    /// remaps the source with a cubic spline through the (input, output) points,
+    /// sorted by input when built with [`NoiseNode::curve`] or deserialized
+    Curve {
+        source: Box<NoiseNode>,
+        #[serde(deserialize_with = "deserialize_curve_points")]
+        points: Vec<(f32, f32)>,
+    },
+    /// `a` where control is below threshold, `b` above it, blended over `falloff`
+    Select {
+        a: Box<NoiseNode>,
+        b: Box<NoiseNode>,
+        control: Box<NoiseNode>,
+        threshold: f32,
+        #[serde(default)]
+        falloff: f32,
+    },
+    /// linear blend from `a` to `b` with control mapped from [-1.0, 1.0] to [0.0, 1.0]
+    Blend {
+        a: Box<NoiseNode>,
+        b: Box<NoiseNode>,
+        control: Box<NoiseNode>,
+    },
+    /// offsets the sample position of the source by two other noise fields,
+    /// see [`DomainWarp`](super::DomainWarp)
+    Warp {
+        source: Box<NoiseNode>,
+        warp_x: Box<NoiseNode>,
+        warp_y: Box<NoiseNode>,
+        strength: f32,
+        #[serde(default = "default_iterations")]
+        iterations: u32,
+    },
+    /// randomly displaces the sample position of the source with fractal perlin noise
+    Turbulence {
+        source: Box<NoiseNode>,
+        noise: PerlinNoise,
+        frequency: f32,
+        power: f32,
+        roughness: u32,
+    },
+}
+
+impl NoiseNode {
+    pub fn from_ron(description: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(description)
+    }
+
+    pub fn from_json(description: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(description)
+    }
+
+    pub fn constant(value: f32) -> Self {
+        Self::Constant(value)
+    }
+
+    pub fn perlin(seed: u64) -> Self {
+        Self::Perlin(PerlinNoise::with_seed(seed))
+    }
+
+    pub fn simplex(seed: u64) -> Self {
+        Self::Simplex(SimplexNoise::with_seed(seed))
+    }
+
+    pub fn value(seed: u64) -> Self {
+        Self::Value(ValueNoise::with_seed(seed))
+    }
+
+    pub fn worley(noise: WorleyNoise) -> Self {
+        Self::Worley(noise)
+    }
+
+    pub fn source(source: impl NoiseSource + Send + Sync + 'static) -> Self {
+        Self::Source(Arc::new(source))
+    }
+
+    pub fn fractal(self, kind: FractalKind, settings: FractalSettings) -> Self {
+        Self::Fractal {
+            source: Box::new(self),
+            kind,
+            settings,
+        }
+    }
+
+    pub fn fbm(self, settings: FractalSettings) -> Self {
+        self.fractal(FractalKind::Fbm, settings)
+    }
+
+    pub fn ridged(self, settings: FractalSettings) -> Self {
+        self.fractal(FractalKind::Ridged, settings)
+    }
+
+    pub fn billow(self, settings: FractalSettings) -> Self {
+        self.fractal(FractalKind::Billow, settings)
+    }
+
+    pub fn hybrid(self, settings: FractalSettings) -> Self {
+        self.fractal(FractalKind::Hybrid, settings)
+    }
+
+    pub fn min(self, other: NoiseNode) -> Self {
+        Self::Min(Box::new(self), Box::new(other))
+    }
+
+    pub fn max(self, other: NoiseNode) -> Self {
+        Self::Max(Box::new(self), Box::new(other))
+    }
+
+    pub fn clamp(self, min: f32, max: f32) -> Self {
+        Self::Clamp {
+            source: Box::new(self),
+            min,
+            max,
+        }
+    }
+
+    pub fn scale_bias(self, scale: f32, bias: f32) -> Self {
+        Self::ScaleBias {
+            source: Box::new(self),
+            scale,
+            bias,
+        }
+    }
+
+    /// Panics if two points have the same input or a point isn't finite.
+    pub fn curve(self, points: Vec<(f32, f32)>) -> Self {
+        Self::Curve {
+            source: Box::new(self),
+            points: sorted_curve_points(points).unwrap_or_else(|err| panic!("{err}")),
+        }
+    }
+
+    /// `self` where control is below threshold, `other` above it
+    pub fn select(
+        self,
+        other: NoiseNode,
+        control: NoiseNode,
+        threshold: f32,
+        falloff: f32,
+    ) -> Self {
+        Self::Select {
+            a: Box::new(self),
+            b: Box::new(other),
+            control: Box::new(control),
+            threshold,
+            falloff,
+        }
+    }
+
+    pub fn blend(self, other: NoiseNode, control: NoiseNode) -> Self {
+        Self::Blend {
+            a: Box::new(self),
+            b: Box::new(other),
+            control: Box::new(control),
+        }
+    }
+
+    pub fn warp(self, warp_x: NoiseNode, warp_y: NoiseNode, strength: f32) -> Self {
+        self.recursive_warp(warp_x, warp_y, strength, 1)
+    }
+
+    pub fn recursive_warp(
+        self,
+        warp_x: NoiseNode,
+        warp_y: NoiseNode,
+        strength: f32,
+        iterations: u32,
+    ) -> Self {
+        Self::Warp {
+            source: Box::new(self),
+            warp_x: Box::new(warp_x),
+            warp_y: Box::new(warp_y),
+            strength,
+            iterations,
+        }
+    }
+
+    pub fn turbulence(self, seed: u64, frequency: f32, power: f32, roughness: u32) -> Self {
+        Self::Turbulence {
+            source: Box::new(self),
+            noise: PerlinNoise::with_seed(seed),
+            frequency,
+            power,
+            roughness,
+        }
+    }
+}
+
+impl Add for NoiseNode {
+    type Output = NoiseNode;
+
+    fn add(self, rhs: NoiseNode) -> NoiseNode {
+        NoiseNode::Add(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl Mul for NoiseNode {
+    type Output = NoiseNode;
+
+    fn mul(self, rhs: NoiseNode) -> NoiseNode {
+        NoiseNode::Multiply(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl NoiseSource for NoiseNode {
+    fn sample2d(&self, x: f32, y: f32) -> f32 {
+        match self {
+            NoiseNode::Constant(value) => *value,
+            NoiseNode::Perlin(noise) => noise.sample2d(x, y),
+            NoiseNode::Simplex(noise) => noise.sample2d(x, y),
+            NoiseNode::Value(noise) => noise.sample2d(x, y),
+            NoiseNode::Worley(noise) => noise.sample2d(x, y),
+            NoiseNode::Source(noise) => noise.sample2d(x, y),
+            NoiseNode::Fractal {
+                source,
+                kind,
+                settings,
+            } => kind.sample(source.as_ref(), x, y, settings),
+            NoiseNode::Add(a, b) => a.sample2d(x, y) + b.sample2d(x, y),
+            NoiseNode::Multiply(a, b) => a.sample2d(x, y) * b.sample2d(x, y),
+            NoiseNode::Min(a, b) => a.sample2d(x, y).min(b.sample2d(x, y)),
+            NoiseNode::Max(a, b) => a.sample2d(x, y).max(b.sample2d(x, y)),
+            NoiseNode::Clamp { source, min, max } => source.sample2d(x, y).clamp(*min, *max),
+            NoiseNode::ScaleBias {
+                source,
+                scale,
+                bias,
+            } => source.sample2d(x, y) * scale + bias,
+            NoiseNode::Curve { source, points } => spline_curve(points, source.sample2d(x, y)),
+            NoiseNode::Select {
+                a,
+                b,
+                control,
+                threshold,
+                falloff,
+            } => {
+                let c = control.sample2d(x, y);
+                if c <= threshold - falloff {
+                    a.sample2d(x, y)
+                } else if c >= threshold + falloff {
+                    b.sample2d(x, y)
+                } else {
+                    let t = (c - (threshold - falloff)) / (2.0 * falloff);
+                    let t = t * t * (3.0 - 2.0 * t);
+                    lerp(a.sample2d(x, y), b.sample2d(x, y), t)
+                }
+            }
+            NoiseNode::Blend { a, b, control } => {
+                let t = ((control.sample2d(x, y) + 1.0) / 2.0).clamp(0.0, 1.0);
+                lerp(a.sample2d(x, y), b.sample2d(x, y), t)
+            }
+            NoiseNode::Warp {
+                source,
+                warp_x,
+                warp_y,
+                strength,
+                iterations,
+            } => {
+                let (wx, wy) = warp_position(
+                    warp_x.as_ref(),
+                    warp_y.as_ref(),
+                    x,
+                    y,
+                    *strength,
+                    *iterations,
+                );
+                source.sample2d(wx, wy)
+            }
+            NoiseNode::Turbulence {
+                source,
+                noise,
+                frequency,
+                power,
+                roughness,
+            } => {
+                let settings = FractalSettings {
+                    octaves: *roughness,
+                    frequency: *frequency,
+                    ..Default::default()
+                };
+                // sample the second field far away so both offsets are uncorrelated
+                let dx = noise.fractal_brownian_motion(x, y, &settings) * power;
+                let dy = noise.fractal_brownian_motion(x + 12.414, y + 65.124, &settings) * power;
+                source.sample2d(x + dx, y + dy)
+            }
+        }
+    }
+
+    /// `None` if any node on the way has no 3d variant, warps are 2d only
+    fn sample3d(&self, x: f32, y: f32, z: f32) -> Option<f32> {
+        let value = match self {
+            NoiseNode::Constant(value) => *value,
+            NoiseNode::Perlin(noise) => noise.noise3d(x, y, z),
+            NoiseNode::Source(noise) => noise.sample3d(x, y, z)?,
+            NoiseNode::Fractal {
+                source,
+                kind,
+                settings,
+            } => kind.sample3d(source.as_ref(), x, y, z, settings)?,
+            NoiseNode::Add(a, b) => a.sample3d(x, y, z)? + b.sample3d(x, y, z)?,
+            NoiseNode::Multiply(a, b) => a.sample3d(x, y, z)? * b.sample3d(x, y, z)?,
+            NoiseNode::Min(a, b) => a.sample3d(x, y, z)?.min(b.sample3d(x, y, z)?),
+            NoiseNode::Max(a, b) => a.sample3d(x, y, z)?.max(b.sample3d(x, y, z)?),
+            NoiseNode::Clamp { source, min, max } => source.sample3d(x, y, z)?.clamp(*min, *max),
+            NoiseNode::ScaleBias {
+                source,
+                scale,
+                bias,
+            } => source.sample3d(x, y, z)? * scale + bias,
+            NoiseNode::Curve { source, points } => spline_curve(points, source.sample3d(x, y, z)?),
+            NoiseNode::Select {
+                a,
+                b,
+                control,
+                threshold,
+                falloff,
+            } => {
+                let c = control.sample3d(x, y, z)?;
+                if c <= threshold - falloff {
+                    a.sample3d(x, y, z)?
+                } else if c >= threshold + falloff {
+                    b.sample3d(x, y, z)?
+                } else {
+                    let t = (c - (threshold - falloff)) / (2.0 * falloff);
+                    let t = t * t * (3.0 - 2.0 * t);
+                    lerp(a.sample3d(x, y, z)?, b.sample3d(x, y, z)?, t)
+                }
+            }
+            NoiseNode::Blend { a, b, control } => {
+                let t = ((control.sample3d(x, y, z)? + 1.0) / 2.0).clamp(0.0, 1.0);
+                lerp(a.sample3d(x, y, z)?, b.sample3d(x, y, z)?, t)
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// analytic where the nodes allow it, central differences otherwise
+    fn sample2d_with_derivative(&self, x: f32, y: f32) -> (f32, Vec2) {
+        match self {
+            NoiseNode::Constant(value) => (*value, Vec2::ZERO),
+            NoiseNode::Perlin(noise) => noise.sample2d_with_derivative(x, y),
+            NoiseNode::Source(noise) => noise.sample2d_with_derivative(x, y),
+            NoiseNode::Fractal {
+                source,
+                kind: FractalKind::Fbm,
+                settings,
+            } => fractal_brownian_motion_with_derivative(source.as_ref(), x, y, settings),
+            NoiseNode::Add(a, b) => {
+                let (va, da) = a.sample2d_with_derivative(x, y);
+                let (vb, db) = b.sample2d_with_derivative(x, y);
+                (va + vb, da + db)
+            }
+            NoiseNode::Multiply(a, b) => {
+                let (va, da) = a.sample2d_with_derivative(x, y);
+                let (vb, db) = b.sample2d_with_derivative(x, y);
+                (va * vb, da * vb + db * va)
+            }
+            NoiseNode::Min(a, b) | NoiseNode::Max(a, b) => {
+                let (va, da) = a.sample2d_with_derivative(x, y);
+                let (vb, db) = b.sample2d_with_derivative(x, y);
+                let take_a = match self {
+                    NoiseNode::Min(..) => va <= vb,
+                    _ => va >= vb,
+                };
+                if take_a {
+                    (va, da)
+                } else {
+                    (vb, db)
+                }
+            }
+            NoiseNode::Clamp { source, min, max } => {
+                let (v, d) = source.sample2d_with_derivative(x, y);
+                if v < *min || v > *max {
+                    (v.clamp(*min, *max), Vec2::ZERO)
+                } else {
+                    (v, d)
+                }
+            }
+            NoiseNode::ScaleBias {
+                source,
+                scale,
+                bias,
+            } => {
+                let (v, d) = source.sample2d_with_derivative(x, y);
+                (v * scale + bias, d * *scale)
+            }
+            _ => finite_difference(self, x, y),
+        }
+    }
+
+    fn fill_grid(&self, origin: Vec2, step: Vec2, width: usize, height: usize, out: &mut [f32]) {
+        let len = width * height;
+        // evaluates both nodes on the grid and combines them sample by sample
+        let combine = |a: &NoiseNode, b: &NoiseNode, out: &mut [f32], f: fn(f32, f32) -> f32| {
+            a.fill_grid(origin, step, width, height, out);
+            let mut other = vec![0.0; len];
+            b.fill_grid(origin, step, width, height, &mut other);
+            for (value, other) in out[..len].iter_mut().zip(other) {
+                *value = f(*value, other);
+            }
+        };
+        match self {
+            NoiseNode::Constant(value) => out[..len].fill(*value),
+            NoiseNode::Perlin(noise) => noise.fill_grid(origin, step, width, height, out),
+            NoiseNode::Fractal {
+                source,
+                kind: FractalKind::Fbm,
+                settings,
+            } => match source.as_ref() {
+                NoiseNode::Perlin(noise) => {
+                    noise.fill_grid_fbm(origin, step, width, height, settings, out)
+                }
+                _ => fill_grid_scalar(self, origin, step, width, height, out),
+            },
+            NoiseNode::Add(a, b) => combine(a, b, out, |a, b| a + b),
+            NoiseNode::Multiply(a, b) => combine(a, b, out, |a, b| a * b),
+            NoiseNode::Min(a, b) => combine(a, b, out, f32::min),
+            NoiseNode::Max(a, b) => combine(a, b, out, f32::max),
+            NoiseNode::Clamp { source, min, max } => {
+                source.fill_grid(origin, step, width, height, out);
+                out[..len].iter_mut().for_each(|v| *v = v.clamp(*min, *max));
+            }
+            NoiseNode::ScaleBias {
+                source,
+                scale,
+                bias,
+            } => {
+                source.fill_grid(origin, step, width, height, out);
+                out[..len].iter_mut().for_each(|v| *v = *v * scale + bias);
+            }
+            NoiseNode::Curve { source, points } => {
+                source.fill_grid(origin, step, width, height, out);
+                out[..len]
+                    .iter_mut()
+                    .for_each(|v| *v = spline_curve(points, *v));
+            }
+            // everything else samples one at a time
+            _ => fill_grid_scalar(self, origin, step, width, height, out),
+        }
+    }
+
+    fn range(&self) -> (f32, f32) {
+        match self {
+            NoiseNode::Constant(value) => (*value, *value),
+            NoiseNode::Perlin(noise) => noise.range(),
+            NoiseNode::Simplex(noise) => noise.range(),
+            NoiseNode::Value(noise) => noise.range(),
+            NoiseNode::Worley(noise) => noise.range(),
+            NoiseNode::Source(noise) => noise.range(),
+            NoiseNode::Fractal {
+                source,
+                kind,
+                settings,
+            } => kind.range(source.range(), settings),
+            NoiseNode::Add(a, b) => {
+                let (a_min, a_max) = a.range();
+                let (b_min, b_max) = b.range();
+                (a_min + b_min, a_max + b_max)
+            }
+            NoiseNode::Multiply(a, b) => {
+                let (a_min, a_max) = a.range();
+                let (b_min, b_max) = b.range();
+                let products = [a_min * b_min, a_min * b_max, a_max * b_min, a_max * b_max];
+                (
+                    products.into_iter().fold(f32::MAX, f32::min),
+                    products.into_iter().fold(f32::MIN, f32::max),
+                )
+            }
+            NoiseNode::Min(a, b) => {
+                let (a_min, a_max) = a.range();
+                let (b_min, b_max) = b.range();
+                (a_min.min(b_min), a_max.min(b_max))
+            }
+            NoiseNode::Max(a, b) => {
+                let (a_min, a_max) = a.range();
+                let (b_min, b_max) = b.range();
+                (a_min.max(b_min), a_max.max(b_max))
+            }
+            NoiseNode::Clamp { source, min, max } => {
+                let (s_min, s_max) = source.range();
+                (s_min.clamp(*min, *max), s_max.clamp(*min, *max))
+            }
+            NoiseNode::ScaleBias {
+                source,
+                scale,
+                bias,
+            } => {
+                let (min, max) = source.range();
+                let (a, b) = (min * scale + bias, max * scale + bias);
+                (a.min(b), a.max(b))
+            }
+            // the spline can overshoot between the points, so this is only approximate
+            NoiseNode::Curve { source, points } => {
+                if points.is_empty() {
+                    return source.range();
+                }
+                points.iter().fold((f32::MAX, f32::MIN), |(min, max), p| {
+                    (min.min(p.1), max.max(p.1))
+                })
+            }
+            NoiseNode::Select { a, b, .. } | NoiseNode::Blend { a, b, .. } => {
+                let (a_min, a_max) = a.range();
+                let (b_min, b_max) = b.range();
+                (a_min.min(b_min), a_max.max(b_max))
+            }
+            NoiseNode::Warp { source, .. } | NoiseNode::Turbulence { source, .. } => source.range(),
+        }
+    }
+}
+
+fn default_iterations() -> u32 {
+    1
+}
+
+/// sorts the points by input, the spline needs distinct and finite inputs
+fn sorted_curve_points(mut points: Vec<(f32, f32)>) -> Result<Vec<(f32, f32)>, String> {
+    if let Some(point) = points
+        .iter()
+        .find(|(x, y)| !x.is_finite() || !y.is_finite())
+    {
+        return Err(format!("curve point {point:?} isn't finite"));
+    }
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if let Some(pair) = points.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(format!(
+            "curve points {:?} and {:?} have the same input",
+            pair[0], pair[1]
+        ));
+    }
+    Ok(points)
+}
+
+fn deserialize_curve_points<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(f32, f32)>, D::Error> {
+    sorted_curve_points(Vec::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// Catmull-Rom spline through the points, constant outside of them.
+fn spline_curve(points: &[(f32, f32)], x: f32) -> f32 {
+    let Some(first) = points.first() else {
+        return x;
+    };
+    let last = points[points.len() - 1];
+    if x <= first.0 {
+        return first.1;
+    }
+    if x >= last.0 {
+        return last.1;
+    }
+    let i = points
+        .iter()
+        .position(|p| p.0 > x)
+        .unwrap_or(points.len() - 1);
+    let p0 = points[i.saturating_sub(2)].1;
+    let p1 = points[i - 1];
+    let p2 = points[i];
+    let p3 = points[(i + 1).min(points.len() - 1)].1;
+    let t = (x - p1.0) / (p2.0 - p1.0);
+    catmull_rom([p0, p1.1, p2.1, p3], t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(value: f32) -> NoiseNode {
+        NoiseNode::constant(value)
+    }
+
+    fn samples(node: &NoiseNode) -> Vec<f32> {
+        (0..64)
+            .map(|i| node.sample2d(i as f32 * 1.37, i as f32 * 0.71))
+            .collect()
+    }
+
+    #[test]
+    fn documented_example_parses_from_ron_and_json() {
+        let ron = "ScaleBias(source: Fractal(source: Perlin(42), settings: (octaves: 6)), scale: 0.5, bias: 0.1)";
+        let json = r#"{"ScaleBias": {"source": {"Fractal": {"source": {"Perlin": 42}, "settings": {"octaves": 6}}}, "scale": 0.5, "bias": 0.1}}"#;
+        let settings = FractalSettings {
+            octaves: 6,
+            ..Default::default()
+        };
+        let built = NoiseNode::perlin(42).fbm(settings).scale_bias(0.5, 0.1);
+        let expected = samples(&built);
+        assert_eq!(samples(&NoiseNode::from_ron(ron).unwrap()), expected);
+        assert_eq!(samples(&NoiseNode::from_json(json).unwrap()), expected);
+    }
+
+    #[test]
+    fn scale_bias_and_clamp() {
+        assert_eq!(constant(0.5).scale_bias(2.0, 0.25).sample2d(0.0, 0.0), 1.25);
+        assert_eq!(constant(0.5).scale_bias(-2.0, 0.0).range(), (-1.0, -1.0));
+        assert_eq!(constant(3.0).clamp(-1.0, 1.0).sample2d(0.0, 0.0), 1.0);
+        assert_eq!(constant(-3.0).clamp(-1.0, 1.0).sample2d(0.0, 0.0), -1.0);
+        assert_eq!(constant(0.3).clamp(-1.0, 1.0).sample2d(0.0, 0.0), 0.3);
+    }
+
+    #[test]
+    fn select_switches_at_the_threshold() {
+        let select = |control: f32| {
+            constant(-1.0)
+                .select(constant(1.0), constant(control), 0.5, 0.1)
+                .sample2d(0.0, 0.0)
+        };
+        assert_eq!(select(0.0), -1.0);
+        assert_eq!(select(0.4), -1.0);
+        assert!(select(0.5).abs() < 1e-6);
+        assert_eq!(select(0.6), 1.0);
+        assert_eq!(select(1.0), 1.0);
+    }
+
+    #[test]
+    fn blend_maps_the_control_to_the_weight() {
+        let blend = |control: f32| {
+            constant(2.0)
+                .blend(constant(4.0), constant(control))
+                .sample2d(0.0, 0.0)
+        };
+        assert_eq!(blend(-1.0), 2.0);
+        assert_eq!(blend(0.0), 3.0);
+        assert_eq!(blend(1.0), 4.0);
+        assert_eq!(blend(5.0), 4.0);
+    }
+
+    #[test]
+    fn curve_goes_through_its_points() {
+        let points = vec![(-1.0, 0.0), (-0.2, 0.1), (0.3, 0.8), (1.0, 1.0)];
+        for (x, y) in points.iter() {
+            let curve = constant(*x).curve(points.clone());
+            assert!((curve.sample2d(0.0, 0.0) - y).abs() < 1e-6);
+        }
+        // constant outside of the points
+        assert_eq!(constant(-2.0).curve(points.clone()).sample2d(0.0, 0.0), 0.0);
+        assert_eq!(constant(2.0).curve(points).sample2d(0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn curve_points_are_sorted() {
+        let sorted = constant(0.1).curve(vec![(-1.0, 0.0), (0.0, 0.5), (1.0, 1.0)]);
+        let unsorted = constant(0.1).curve(vec![(1.0, 1.0), (-1.0, 0.0), (0.0, 0.5)]);
+        assert_eq!(unsorted.sample2d(0.0, 0.0), sorted.sample2d(0.0, 0.0));
+        let ron = "Curve(source: Constant(0.1), points: [(1.0, 1.0), (-1.0, 0.0), (0.0, 0.5)])";
+        let deserialized = NoiseNode::from_ron(ron).unwrap();
+        assert_eq!(deserialized.sample2d(0.0, 0.0), sorted.sample2d(0.0, 0.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "same input")]
+    fn curve_rejects_duplicate_inputs() {
+        constant(0.0).curve(vec![(0.0, 0.0), (0.0, 1.0)]);
+    }
+
+    #[test]
+    fn deserializing_rejects_duplicate_inputs() {
+        let ron = "Curve(source: Constant(0.0), points: [(0.0, 0.0), (0.0, 1.0)])";
+        assert!(NoiseNode::from_ron(ron).is_err());
+    }
+}
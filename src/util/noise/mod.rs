@@ -0,0 +1,422 @@
+mod batch;
+mod fractal;
+mod graph;
+mod periodic;
+mod perlin3d;
+mod simplex;
+mod value;
+mod warp;
+mod worley;
+
+use bevy::math::Vec2;
+use serde::Deserialize;
+
+use super::rng::SplitMix64;
+
+pub use fractal::{
+    billow, eroded_fbm, fractal_brownian_motion, fractal_brownian_motion_with_derivative,
+    hybrid_multifractal, ridged_multifractal, Fbm, FractalKind, FractalSettings,
+};
+pub use graph::NoiseNode;
+pub use periodic::{Period, PeriodicNoise, Tiled};
+pub use simplex::SimplexNoise;
+pub use value::ValueNoise;
+pub use warp::{warp_position, DomainWarp, WarpSettings};
+pub use worley::{WorleyMode, WorleyNoise};
+
+// Hash lookup table as defined by Ken Perlin. This is a randomly arranged array of all numbers from 0-255 inclusive.
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+/// A source of coherent noise that terrain generation can sample.
+pub trait NoiseSource {
+    fn sample2d(&self, x: f32, y: f32) -> f32;
+
+    /// the value and its (d/dx, d/dy) derivative, central differences unless the
+    /// source has an analytic derivative
+    fn sample2d_with_derivative(&self, x: f32, y: f32) -> (f32, Vec2) {
+        finite_difference(self, x, y)
+    }
+
+    /// `None` if the source has no 3d variant
+    fn sample3d(&self, _x: f32, _y: f32, _z: f32) -> Option<f32> {
+        None
+    }
+
+    /// the (min, max) range of the sampled values
+    fn range(&self) -> (f32, f32);
+
+    /// Fills `out` row by row with samples at `origin + step * (column, row)`.
+    /// Sources with a batch path override it, the results are the same as sampling
+    /// one at a time.
+    fn fill_grid(&self, origin: Vec2, step: Vec2, width: usize, height: usize, out: &mut [f32]) {
+        fill_grid_scalar(self, origin, step, width, height, out);
+    }
+}
+
+impl<N: NoiseSource + ?Sized> NoiseSource for &N {
+    fn sample2d(&self, x: f32, y: f32) -> f32 {
+        (**self).sample2d(x, y)
+    }
+
+    fn sample2d_with_derivative(&self, x: f32, y: f32) -> (f32, Vec2) {
+        (**self).sample2d_with_derivative(x, y)
+    }
+
+    fn sample3d(&self, x: f32, y: f32, z: f32) -> Option<f32> {
+        (**self).sample3d(x, y, z)
+    }
+
+    fn range(&self) -> (f32, f32) {
+        (**self).range()
+    }
+
+    fn fill_grid(&self, origin: Vec2, step: Vec2, width: usize, height: usize, out: &mut [f32]) {
+        (**self).fill_grid(origin, step, width, height, out)
+    }
+}
+
+/// [`NoiseSource::fill_grid`] one sample at a time
+pub(super) fn fill_grid_scalar<N: NoiseSource + ?Sized>(
+    noise: &N,
+    origin: Vec2,
+    step: Vec2,
+    width: usize,
+    height: usize,
+    out: &mut [f32],
+) {
+    for row in 0..height {
+        for column in 0..width {
+            out[row * width + column] = noise.sample2d(
+                origin.x + column as f32 * step.x,
+                origin.y + row as f32 * step.y,
+            );
+        }
+    }
+}
+
+/// value and central difference derivative of any noise source
+pub fn finite_difference<N: NoiseSource + ?Sized>(noise: &N, x: f32, y: f32) -> (f32, Vec2) {
+    let d = 0.01;
+    let dx = noise.sample2d(x + d, y) - noise.sample2d(x - d, y);
+    let dy = noise.sample2d(x, y + d) - noise.sample2d(x, y - d);
+    (noise.sample2d(x, y), Vec2::new(dx, dy) / (2.0 * d))
+}
+
+/// Doubled permutation table, so lookups like `p[p[x] + y]` need no extra wrapping.
+#[derive(Clone)]
+struct PermutationTable {
+    p: [u8; 512],
+}
+
+impl PermutationTable {
+    fn new() -> Self {
+        Self::from_permutation(PERMUTATION)
+    }
+
+    /// Shuffles the 0..=255 table with a seeded Fisher-Yates shuffle. The same seed
+    /// results in the same table on every platform.
+    fn with_seed(seed: u64) -> Self {
+        let mut permutation = [0; 256];
+        for (i, v) in permutation.iter_mut().enumerate() {
+            *v = i as u8;
+        }
+        let mut rng = SplitMix64::new(seed);
+        for i in (1..256).rev() {
+            let j = rng.next_bounded(i as u64 + 1) as usize;
+            permutation.swap(i, j);
+        }
+        Self::from_permutation(permutation)
+    }
+
+    fn from_permutation(permutation: [u8; 256]) -> Self {
+        let mut p = [0; 512];
+        for (i, v) in p.iter_mut().enumerate() {
+            *v = permutation[i % 256];
+        }
+        Self { p }
+    }
+
+    /// hash of a lattice point, `x` and `y` must be in 0..=256
+    fn hash2(&self, x: usize, y: usize) -> u8 {
+        self.p[self.p[x] as usize + y]
+    }
+}
+
+/// Deserializes from its seed.
+#[derive(Clone, Deserialize)]
+#[serde(from = "u64")]
+pub struct PerlinNoise {
+    p: PermutationTable,
+}
+
+impl From<u64> for PerlinNoise {
+    fn from(seed: u64) -> Self {
+        Self::with_seed(seed)
+    }
+}
+
+impl Default for PerlinNoise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoiseSource for PerlinNoise {
+    fn sample2d(&self, x: f32, y: f32) -> f32 {
+        self.noise2d(x, y)
+    }
+
+    fn sample2d_with_derivative(&self, x: f32, y: f32) -> (f32, Vec2) {
+        self.noise2d_with_derivative(x, y)
+    }
+
+    fn sample3d(&self, x: f32, y: f32, z: f32) -> Option<f32> {
+        Some(self.noise3d(x, y, z))
+    }
+
+    fn range(&self) -> (f32, f32) {
+        (-1.0, 1.0)
+    }
+
+    fn fill_grid(&self, origin: Vec2, step: Vec2, width: usize, height: usize, out: &mut [f32]) {
+        PerlinNoise::fill_grid(self, origin, step, width, height, out)
+    }
+}
+
+impl PerlinNoise {
+    pub fn new() -> Self {
+        Self {
+            p: PermutationTable::new(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            p: PermutationTable::with_seed(seed),
+        }
+    }
+
+    pub fn noise2d_with_freq(&self, x: f32, y: f32, frequency: f32) -> f32 {
+        self.noise2d(x * frequency, y * frequency)
+    }
+
+    pub fn fractal_brownian_motion(&self, x: f32, y: f32, settings: &FractalSettings) -> f32 {
+        fractal_brownian_motion(self, x, y, settings)
+    }
+
+    /// fbm that repeats after `period` sample units
+    pub fn fractal_brownian_motion_periodic(
+        &self,
+        x: f32,
+        y: f32,
+        period: Period,
+        settings: &FractalSettings,
+    ) -> f32 {
+        FractalKind::Fbm.sample_periodic(self, x, y, period, settings)
+    }
+
+    pub fn ridged_multifractal(&self, x: f32, y: f32, settings: &FractalSettings) -> f32 {
+        ridged_multifractal(self, x, y, settings)
+    }
+
+    pub fn billow(&self, x: f32, y: f32, settings: &FractalSettings) -> f32 {
+        billow(self, x, y, settings)
+    }
+
+    pub fn hybrid_multifractal(&self, x: f32, y: f32, settings: &FractalSettings) -> f32 {
+        hybrid_multifractal(self, x, y, settings)
+    }
+
+    /// a noise with range [-1.0, 1.0]
+    pub fn noise2d(&self, x: f32, y: f32) -> f32 {
+        let xi = index_wrap(x.floor() as isize, 256);
+        let yi = index_wrap(y.floor() as isize, 256);
+        self.lattice_noise2d(x, y, (xi, xi + 1), (yi, yi + 1))
+    }
+
+    /// [`noise2d`](Self::noise2d) with a lattice that repeats after the given period
+    pub fn noise2d_periodic(&self, x: f32, y: f32, period: Period) -> f32 {
+        let xi = periodic::lattice_indices(x, period.x);
+        let yi = periodic::lattice_indices(y, period.y);
+        self.lattice_noise2d(x, y, xi, yi)
+    }
+
+    /// noise from the hash table indices of the surrounding lattice points
+    fn lattice_noise2d(
+        &self,
+        x: f32,
+        y: f32,
+        (x0, x1): (usize, usize),
+        (y0, y1): (usize, usize),
+    ) -> f32 {
+        let tx = x - x.floor();
+        let ty = y - y.floor();
+        // get hash function value at the 4 surrounding lattice points
+        let top_right = (tx - 1.0, ty - 1.0);
+        let top_left = (tx, ty - 1.0);
+        let bottom_right = (tx - 1.0, ty);
+        let bottom_left = (tx, ty);
+
+        let val_top_right = self.p.hash2(x1, y1);
+        let val_top_left = self.p.hash2(x0, y1);
+        let val_bottom_right = self.p.hash2(x1, y0);
+        let val_bottom_left = self.p.hash2(x0, y0);
+
+        let dot_top_right = dot(top_right, get_vec(val_top_right));
+        let dot_top_left = dot(top_left, get_vec(val_top_left));
+        let dot_bottom_right = dot(bottom_right, get_vec(val_bottom_right));
+        let dot_bottom_left = dot(bottom_left, get_vec(val_bottom_left));
+        //smoothstep
+        let smooth_tx = smoothstep(tx);
+        let smooth_ty = smoothstep(ty);
+        // lerp
+        let s = lerp(dot_bottom_left, dot_top_left, smooth_ty);
+        let n = lerp(dot_bottom_right, dot_top_right, smooth_ty);
+        lerp(s, n, smooth_tx)
+    }
+}
+
+impl PerlinNoise {
+    /// [`noise2d`](Self::noise2d) and its analytic (d/dx, d/dy) derivative
+    pub fn noise2d_with_derivative(&self, x: f32, y: f32) -> (f32, Vec2) {
+        let xi = index_wrap(x.floor() as isize, 256);
+        let yi = index_wrap(y.floor() as isize, 256);
+
+        let tx = x - x.floor();
+        let ty = y - y.floor();
+
+        let g_top_right = get_vec(self.p.hash2(xi + 1, yi + 1));
+        let g_top_left = get_vec(self.p.hash2(xi, yi + 1));
+        let g_bottom_right = get_vec(self.p.hash2(xi + 1, yi));
+        let g_bottom_left = get_vec(self.p.hash2(xi, yi));
+
+        let dot_top_right = dot((tx - 1.0, ty - 1.0), g_top_right);
+        let dot_top_left = dot((tx, ty - 1.0), g_top_left);
+        let dot_bottom_right = dot((tx - 1.0, ty), g_bottom_right);
+        let dot_bottom_left = dot((tx, ty), g_bottom_left);
+
+        let u = smoothstep(tx);
+        let v = smoothstep(ty);
+        let du = smoothstep_derivative(tx);
+        let dv = smoothstep_derivative(ty);
+        // same interpolation as noise2d, so the values are identical
+        let s = lerp(dot_bottom_left, dot_top_left, v);
+        let n = lerp(dot_bottom_right, dot_top_right, v);
+        let value = lerp(s, n, u);
+
+        // derivative of the expanded bilinear form
+        // a + u(b - a) + v(c - a) + uv(a - b - c + d)
+        let (a, b, c, d) = (
+            dot_bottom_left,
+            dot_bottom_right,
+            dot_top_left,
+            dot_top_right,
+        );
+        let (ga, gb, gc, gd) = (g_bottom_left, g_bottom_right, g_top_left, g_top_right);
+        let k = a - b - c + d;
+        let dx = ga.0
+            + u * (gb.0 - ga.0)
+            + v * (gc.0 - ga.0)
+            + u * v * (ga.0 - gb.0 - gc.0 + gd.0)
+            + du * ((b - a) + v * k);
+        let dy = ga.1
+            + u * (gb.1 - ga.1)
+            + v * (gc.1 - ga.1)
+            + u * v * (ga.1 - gb.1 - gc.1 + gd.1)
+            + dv * ((c - a) + u * k);
+        (value, Vec2::new(dx, dy))
+    }
+}
+
+fn index_wrap(i: isize, size: usize) -> usize {
+    ((i % size as isize) + size as isize) as usize % size
+}
+
+fn dot(v0: (f32, f32), v1: (f32, f32)) -> f32 {
+    v0.0 * v1.0 + v0.1 * v1.1
+}
+
+pub fn smoothstep(t: f32) -> f32 {
+    ((6.0 * t - 15.0) * t + 10.0) * t * t * t
+}
+
+fn smoothstep_derivative(t: f32) -> f32 {
+    30.0 * t * t * (t - 1.0) * (t - 1.0)
+}
+
+fn lerp(start: f32, end: f32, t: f32) -> f32 {
+    start + t * (end - start)
+}
+
+fn get_vec(value: u8) -> (f32, f32) {
+    match value & 3 {
+        0 => (1.0, 1.0),
+        1 => (-1.0, 1.0),
+        2 => (-1.0, -1.0),
+        _ => (1.0, -1.0),
+    }
+}
+
+/// points on a dense grid around the origin whose step doesn't divide the lattice,
+/// so every cell is hit at many offsets
+#[cfg(test)]
+pub(super) fn dense_grid() -> impl Iterator<Item = (f32, f32)> {
+    (0..300)
+        .flat_map(|y| (0..300).map(move |x| (x as f32 * 0.0731 - 11.0, y as f32 * 0.0731 - 11.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heights(noise: &PerlinNoise) -> Vec<f32> {
+        let settings = FractalSettings::default();
+        (0..32)
+            .flat_map(|y| (0..32).map(move |x| (x as f32 * 3.7, y as f32 * 3.7)))
+            .map(|(x, y)| noise.fractal_brownian_motion(x, y, &settings))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_permutation_and_heights() {
+        let (a, b) = (PerlinNoise::with_seed(1234), PerlinNoise::with_seed(1234));
+        assert_eq!(a.p.p, b.p.p);
+        let (a, b) = (heights(&a), heights(&b));
+        assert!(a.iter().zip(&b).all(|(a, b)| a.to_bits() == b.to_bits()));
+    }
+
+    #[test]
+    fn different_seed_different_permutation_and_heights() {
+        let (a, b) = (PerlinNoise::with_seed(1), PerlinNoise::with_seed(2));
+        assert_ne!(a.p.p, b.p.p);
+        assert_ne!(heights(&a), heights(&b));
+    }
+
+    #[test]
+    fn seeded_table_is_a_permutation() {
+        let noise = PerlinNoise::with_seed(99);
+        let mut values = noise.p.p[..256].to_vec();
+        values.sort_unstable();
+        assert!(values.iter().enumerate().all(|(i, v)| i == *v as usize));
+        assert_eq!(noise.p.p[..256], noise.p.p[256..]);
+    }
+
+    #[test]
+    fn unseeded_uses_ken_perlins_table() {
+        assert_eq!(PerlinNoise::new().p.p[..256], PERMUTATION);
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    index_wrap, FractalKind, FractalSettings, NoiseSource, PerlinNoise, ValueNoise, WorleyNoise,
+};
+
+/// Length after which a noise repeats, per axis. `None` for an axis that does not repeat.
+///
+/// On the lattice level the period is rounded to whole lattice cells.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Period {
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+}
+
+impl Period {
+    pub fn new(x: Option<f32>, y: Option<f32>) -> Self {
+        Self { x, y }
+    }
+}
+
+/// A noise source with a lattice that can repeat after an arbitrary period.
+pub trait PeriodicNoise: NoiseSource {
+    fn sample2d_periodic(&self, x: f32, y: f32, period: Period) -> f32;
+}
+
+impl PeriodicNoise for PerlinNoise {
+    fn sample2d_periodic(&self, x: f32, y: f32, period: Period) -> f32 {
+        self.noise2d_periodic(x, y, period)
+    }
+}
+
+impl PeriodicNoise for ValueNoise {
+    fn sample2d_periodic(&self, x: f32, y: f32, period: Period) -> f32 {
+        self.noise2d_periodic(x, y, period)
+    }
+}
+
+impl PeriodicNoise for WorleyNoise {
+    fn sample2d_periodic(&self, x: f32, y: f32, period: Period) -> f32 {
+        self.noise2d_periodic(x, y, period)
+    }
+}
+
+/// A fractal that repeats exactly after `period` sample units. Each octave frequency
+/// is rounded so a whole number of lattice cells fits into the period.
+#[derive(Clone)]
+pub struct Tiled<N> {
+    pub source: N,
+    pub kind: FractalKind,
+    pub settings: FractalSettings,
+    pub period: Period,
+}
+
+impl<N: PeriodicNoise> Tiled<N> {
+    pub fn new(source: N, kind: FractalKind, settings: FractalSettings, period: Period) -> Self {
+        Self {
+            source,
+            kind,
+            settings,
+            period,
+        }
+    }
+}
+
+impl<N: PeriodicNoise> NoiseSource for Tiled<N> {
+    fn sample2d(&self, x: f32, y: f32) -> f32 {
+        self.kind
+            .sample_periodic(&self.source, x, y, self.period, &self.settings)
+    }
+
+    fn range(&self) -> (f32, f32) {
+        self.kind.range(self.source.range(), &self.settings)
+    }
+}
+
+/// the octave frequency and lattice period that tile a noise with the given period
+pub(super) fn periodic_frequency(frequency: f32, period: Option<f32>) -> (f32, Option<f32>) {
+    match period {
+        Some(period) => {
+            let cells = (period * frequency).round().max(1.0);
+            (cells / period, Some(cells))
+        }
+        None => (frequency, None),
+    }
+}
+
+/// hash table indices of the lattice points left and right of `v`
+pub(super) fn lattice_indices(v: f32, period: Option<f32>) -> (usize, usize) {
+    let i = v.floor() as isize;
+    match period {
+        Some(_) => (wrap_cell(i, period), wrap_cell(i + 1, period)),
+        None => {
+            let i = index_wrap(i, 256);
+            (i, i + 1)
+        }
+    }
+}
+
+/// hash table index of a lattice cell
+pub(super) fn wrap_cell(i: isize, period: Option<f32>) -> usize {
+    match period {
+        Some(period) => {
+            let cells = period.round().max(1.0) as isize;
+            i.rem_euclid(cells) as usize % 256
+        }
+        None => index_wrap(i, 256),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_cell_wraps_negative_cells() {
+        assert_eq!(wrap_cell(-1, Some(10.0)), 9);
+        assert_eq!(wrap_cell(10, Some(10.0)), 0);
+        assert_eq!(wrap_cell(-1, None), 255);
+    }
+
+    #[test]
+    fn periodic_frequency_fits_whole_cells() {
+        let (frequency, cells) = periodic_frequency(0.013, Some(100.0));
+        assert_eq!(cells, Some(1.0));
+        assert_eq!(frequency, 0.01);
+        assert_eq!(periodic_frequency(0.013, None), (0.013, None));
+    }
+
+    #[test]
+    fn periodic_noise_repeats() {
+        let noise = PerlinNoise::with_seed(3);
+        let period = Period::new(Some(8.0), Some(5.0));
+        for i in 0..50 {
+            let (x, y) = (i as f32 * 0.37, i as f32 * 0.61);
+            let a = noise.noise2d_periodic(x, y, period);
+            let b = noise.noise2d_periodic(x + 8.0, y - 5.0, period);
+            assert!((a - b).abs() < 1e-4, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn tiled_fractal_repeats() {
+        let settings = FractalSettings {
+            octaves: 4,
+            frequency: 0.05,
+            ..Default::default()
+        };
+        let period = Period::new(Some(122.0), None);
+        let tiled = Tiled::new(
+            PerlinNoise::with_seed(3),
+            FractalKind::Fbm,
+            settings,
+            period,
+        );
+        for i in 0..50 {
+            let (x, y) = (i as f32 * 2.3, i as f32 * 1.7);
+            let (a, b) = (tiled.sample2d(x, y), tiled.sample2d(x - 122.0, y));
+            assert!((a - b).abs() < 1e-3, "{a} != {b}");
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! 3d and 4d variants of the improved perlin noise, e.g. for density fields or
+//! 2d and 3d fields that are animated over time.
+
+use super::{
+    fractal::fbm_octaves, index_wrap, lerp, smoothstep, FractalSettings, PerlinNoise,
+    PermutationTable,
+};
+
+impl PermutationTable {
+    /// hash of a 3d lattice point, all coordinates must be in 0..=256
+    fn hash3(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.p[self.hash2(x, y) as usize + z]
+    }
+
+    /// hash of a 4d lattice point, all coordinates must be in 0..=256
+    fn hash4(&self, x: usize, y: usize, z: usize, w: usize) -> u8 {
+        self.p[self.hash3(x, y, z) as usize + w]
+    }
+}
+
+impl PerlinNoise {
+    /// a noise with range [-1.0, 1.0]
+    pub fn noise3d(&self, x: f32, y: f32, z: f32) -> f32 {
+        let xi = index_wrap(x.floor() as isize, 256);
+        let yi = index_wrap(y.floor() as isize, 256);
+        let zi = index_wrap(z.floor() as isize, 256);
+        let tx = x - x.floor();
+        let ty = y - y.floor();
+        let tz = z - z.floor();
+
+        let corner = |dx: usize, dy: usize, dz: usize| {
+            let hash = self.p.hash3(xi + dx, yi + dy, zi + dz);
+            grad3(hash, tx - dx as f32, ty - dy as f32, tz - dz as f32)
+        };
+
+        let u = smoothstep(tx);
+        let v = smoothstep(ty);
+        let w = smoothstep(tz);
+        let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u);
+        let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u);
+        let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u);
+        let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u);
+        let y0 = lerp(x00, x10, v);
+        let y1 = lerp(x01, x11, v);
+        lerp(y0, y1, w) * SCALE_3D
+    }
+
+    /// a noise with range [-1.0, 1.0]
+    pub fn noise4d(&self, x: f32, y: f32, z: f32, w: f32) -> f32 {
+        let xi = index_wrap(x.floor() as isize, 256);
+        let yi = index_wrap(y.floor() as isize, 256);
+        let zi = index_wrap(z.floor() as isize, 256);
+        let wi = index_wrap(w.floor() as isize, 256);
+        let tx = x - x.floor();
+        let ty = y - y.floor();
+        let tz = z - z.floor();
+        let tw = w - w.floor();
+
+        let corner = |dx: usize, dy: usize, dz: usize, dw: usize| {
+            let hash = self.p.hash4(xi + dx, yi + dy, zi + dz, wi + dw);
+            grad4(
+                hash,
+                tx - dx as f32,
+                ty - dy as f32,
+                tz - dz as f32,
+                tw - dw as f32,
+            )
+        };
+        let u = smoothstep(tx);
+        let v = smoothstep(ty);
+        let s = smoothstep(tz);
+        // interpolate the 3d cube for both w layers
+        let cube = |dw: usize| {
+            let x00 = lerp(corner(0, 0, 0, dw), corner(1, 0, 0, dw), u);
+            let x10 = lerp(corner(0, 1, 0, dw), corner(1, 1, 0, dw), u);
+            let x01 = lerp(corner(0, 0, 1, dw), corner(1, 0, 1, dw), u);
+            let x11 = lerp(corner(0, 1, 1, dw), corner(1, 1, 1, dw), u);
+            lerp(lerp(x00, x10, v), lerp(x01, x11, v), s)
+        };
+        lerp(cube(0), cube(1), smoothstep(tw)) * SCALE_4D
+    }
+
+    pub fn fractal_brownian_motion_3d(
+        &self,
+        x: f32,
+        y: f32,
+        z: f32,
+        settings: &FractalSettings,
+    ) -> f32 {
+        fbm_octaves(settings, |frequency| {
+            self.noise3d(x * frequency, y * frequency, z * frequency)
+        })
+    }
+
+    pub fn fractal_brownian_motion_4d(
+        &self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        settings: &FractalSettings,
+    ) -> f32 {
+        fbm_octaves(settings, |frequency| {
+            self.noise4d(x * frequency, y * frequency, z * frequency, w * frequency)
+        })
+    }
+}
+
+// scale the raw noise to about [-1.0, 1.0]
+const SCALE_3D: f32 = 0.94;
+const SCALE_4D: f32 = 0.85;
+
+/// dot product with one of the 12 cube edge directions from Ken Perlin's improved noise
+fn grad3(hash: u8, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    let u = if h & 1 == 0 { u } else { -u };
+    let v = if h & 2 == 0 { v } else { -v };
+    u + v
+}
+
+/// dot product with one of the 32 edge directions of a 4d hypercube
+fn grad4(hash: u8, x: f32, y: f32, z: f32, w: f32) -> f32 {
+    let h = hash & 31;
+    let a = if h < 24 { x } else { y };
+    let b = if h < 16 { y } else { z };
+    let c = if h < 8 { z } else { w };
+    let a = if h & 1 == 0 { a } else { -a };
+    let b = if h & 2 == 0 { b } else { -b };
+    let c = if h & 4 == 0 { c } else { -c };
+    a + b + c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise::dense_grid;
+
+    const OFFSETS: [f32; 4] = [0.0, 0.37, 0.5, 1.71];
+
+    #[test]
+    fn same_seed_same_noise() {
+        let (a, b) = (PerlinNoise::with_seed(5), PerlinNoise::with_seed(5));
+        let other = PerlinNoise::with_seed(6);
+        assert!(dense_grid().all(|(x, z)| a.noise3d(x, 0.3, z) == b.noise3d(x, 0.3, z)));
+        assert!(dense_grid().all(|(x, z)| a.noise4d(x, 0.3, z, 0.6) == b.noise4d(x, 0.3, z, 0.6)));
+        assert!(dense_grid().any(|(x, z)| a.noise3d(x, 0.3, z) != other.noise3d(x, 0.3, z)));
+        assert!(
+            dense_grid().any(|(x, z)| a.noise4d(x, 0.3, z, 0.6) != other.noise4d(x, 0.3, z, 0.6))
+        );
+    }
+
+    #[test]
+    fn stays_in_range() {
+        let noise = PerlinNoise::with_seed(5);
+        for offset in OFFSETS {
+            for (x, z) in dense_grid() {
+                let value = noise.noise3d(x, offset, z);
+                assert!(
+                    (-1.0..=1.0).contains(&value),
+                    "3d at ({x}, {offset}, {z}) is {value}"
+                );
+                let value = noise.noise4d(x, offset, z, offset);
+                assert!(
+                    (-1.0..=1.0).contains(&value),
+                    "4d at ({x}, {offset}, {z}) is {value}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zero_slices_are_continuous() {
+        let noise = PerlinNoise::with_seed(5);
+        let d = 1e-3;
+        for (x, z) in dense_grid() {
+            let value = noise.noise3d(x, 0.0, z);
+            assert!((noise.noise3d(x + d, 0.0, z) - value).abs() < 0.01);
+            assert!((noise.noise3d(x, 0.0, z + d) - value).abs() < 0.01);
+            let value = noise.noise4d(x, z, 0.3, 0.0);
+            assert!((noise.noise4d(x + d, z, 0.3, 0.0) - value).abs() < 0.01);
+            assert!((noise.noise4d(x, z + d, 0.3, 0.0) - value).abs() < 0.01);
+        }
+        // across the lattice planes
+        for i in -5..5 {
+            let (a, b) = (i as f32 - d, i as f32 + d);
+            assert!((noise.noise3d(a, 0.0, 0.4) - noise.noise3d(b, 0.0, 0.4)).abs() < 0.01);
+            assert!((noise.noise3d(0.4, a, 0.7) - noise.noise3d(0.4, b, 0.7)).abs() < 0.01);
+            assert!(
+                (noise.noise4d(0.4, 0.7, 0.2, a) - noise.noise4d(0.4, 0.7, 0.2, b)).abs() < 0.01
+            );
+        }
+    }
+}
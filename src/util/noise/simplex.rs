@@ -0,0 +1,127 @@
+use serde::Deserialize;
+
+use super::{index_wrap, NoiseSource, PermutationTable};
+
+// skew and unskew factors of the 2d simplex lattice, (sqrt(3) - 1) / 2 and (3 - sqrt(3)) / 6
+const SKEW_2D: f32 = 0.366_025_42;
+const UNSKEW_2D: f32 = 0.211_324_87;
+// squared radius of a lattice point's contribution
+const RADIUS_SQUARED: f32 = 0.5;
+// scales the sum of contributions to about [-1.0, 1.0]
+const NORMALIZER: f32 = 99.2;
+
+const DIAG: f32 = std::f32::consts::FRAC_1_SQRT_2;
+const GRADIENTS: [(f32, f32); 8] = [
+    (1.0, 0.0),
+    (DIAG, DIAG),
+    (0.0, 1.0),
+    (-DIAG, DIAG),
+    (-1.0, 0.0),
+    (-DIAG, -DIAG),
+    (0.0, -1.0),
+    (DIAG, -DIAG),
+];
+
+/// Classic 2d simplex noise after Stefan Gustavson's "Simplex noise demystified", on
+/// a skewed triangular lattice. It has fewer directional artifacts than perlin noise.
+/// Deserializes from its seed.
+#[derive(Clone, Deserialize)]
+#[serde(from = "u64")]
+pub struct SimplexNoise {
+    p: PermutationTable,
+}
+
+impl From<u64> for SimplexNoise {
+    fn from(seed: u64) -> Self {
+        Self::with_seed(seed)
+    }
+}
+
+impl Default for SimplexNoise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimplexNoise {
+    pub fn new() -> Self {
+        Self {
+            p: PermutationTable::new(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            p: PermutationTable::with_seed(seed),
+        }
+    }
+
+    /// a noise with range [-1.0, 1.0]
+    pub fn noise2d(&self, x: f32, y: f32) -> f32 {
+        // skew the input to find the simplex cell
+        let s = (x + y) * SKEW_2D;
+        let i = (x + s).floor();
+        let j = (y + s).floor();
+        let t = (i + j) * UNSKEW_2D;
+        // distance to the cell origin in unskewed space
+        let x0 = x - (i - t);
+        let y0 = y - (j - t);
+        // the cell is split in a lower and an upper triangle
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let x1 = x0 - i1 as f32 + UNSKEW_2D;
+        let y1 = y0 - j1 as f32 + UNSKEW_2D;
+        let x2 = x0 - 1.0 + 2.0 * UNSKEW_2D;
+        let y2 = y0 - 1.0 + 2.0 * UNSKEW_2D;
+
+        let ii = index_wrap(i as isize, 256);
+        let jj = index_wrap(j as isize, 256);
+        let n0 = self.contribution(self.p.hash2(ii, jj), x0, y0);
+        let n1 = self.contribution(self.p.hash2(ii + i1, jj + j1), x1, y1);
+        let n2 = self.contribution(self.p.hash2(ii + 1, jj + 1), x2, y2);
+        NORMALIZER * (n0 + n1 + n2)
+    }
+
+    fn contribution(&self, hash: u8, x: f32, y: f32) -> f32 {
+        let a = RADIUS_SQUARED - x * x - y * y;
+        if a <= 0.0 {
+            return 0.0;
+        }
+        let g = GRADIENTS[(hash & 7) as usize];
+        let a2 = a * a;
+        a2 * a2 * (g.0 * x + g.1 * y)
+    }
+}
+
+impl NoiseSource for SimplexNoise {
+    fn sample2d(&self, x: f32, y: f32) -> f32 {
+        self.noise2d(x, y)
+    }
+
+    fn range(&self) -> (f32, f32) {
+        (-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise::dense_grid;
+
+    #[test]
+    fn same_seed_same_noise() {
+        let (a, b) = (SimplexNoise::with_seed(5), SimplexNoise::with_seed(5));
+        let other = SimplexNoise::with_seed(6);
+        assert!(dense_grid().all(|(x, y)| a.sample2d(x, y) == b.sample2d(x, y)));
+        assert!(dense_grid().any(|(x, y)| a.sample2d(x, y) != other.sample2d(x, y)));
+    }
+
+    #[test]
+    fn stays_in_range() {
+        let noise = SimplexNoise::with_seed(5);
+        let (min, max) = noise.range();
+        for (x, y) in dense_grid() {
+            let value = noise.sample2d(x, y);
+            assert!((min..=max).contains(&value), "({x}, {y}) is {value}");
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use serde::Deserialize;
+
+use super::{index_wrap, lerp, periodic, smoothstep, NoiseSource, Period, PermutationTable};
+
+/// Interpolates random values at the lattice points. Cheaper than gradient noise
+/// but more blocky.
+/// Deserializes from its seed.
+#[derive(Clone, Deserialize)]
+#[serde(from = "u64")]
+pub struct ValueNoise {
+    p: PermutationTable,
+}
+
+impl From<u64> for ValueNoise {
+    fn from(seed: u64) -> Self {
+        Self::with_seed(seed)
+    }
+}
+
+impl Default for ValueNoise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValueNoise {
+    pub fn new() -> Self {
+        Self {
+            p: PermutationTable::new(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            p: PermutationTable::with_seed(seed),
+        }
+    }
+
+    /// a noise with range [-1.0, 1.0]
+    pub fn noise2d(&self, x: f32, y: f32) -> f32 {
+        let xi = index_wrap(x.floor() as isize, 256);
+        let yi = index_wrap(y.floor() as isize, 256);
+        self.lattice_noise2d(x, y, (xi, xi + 1), (yi, yi + 1))
+    }
+
+    /// [`noise2d`](Self::noise2d) with a lattice that repeats after the given period
+    pub fn noise2d_periodic(&self, x: f32, y: f32, period: Period) -> f32 {
+        let xi = periodic::lattice_indices(x, period.x);
+        let yi = periodic::lattice_indices(y, period.y);
+        self.lattice_noise2d(x, y, xi, yi)
+    }
+
+    fn lattice_noise2d(
+        &self,
+        x: f32,
+        y: f32,
+        (x0, x1): (usize, usize),
+        (y0, y1): (usize, usize),
+    ) -> f32 {
+        let tx = smoothstep(x - x.floor());
+        let ty = smoothstep(y - y.floor());
+
+        let bottom_left = lattice_value(self.p.hash2(x0, y0));
+        let bottom_right = lattice_value(self.p.hash2(x1, y0));
+        let top_left = lattice_value(self.p.hash2(x0, y1));
+        let top_right = lattice_value(self.p.hash2(x1, y1));
+
+        let s = lerp(bottom_left, top_left, ty);
+        let n = lerp(bottom_right, top_right, ty);
+        lerp(s, n, tx)
+    }
+}
+
+impl NoiseSource for ValueNoise {
+    fn sample2d(&self, x: f32, y: f32) -> f32 {
+        self.noise2d(x, y)
+    }
+
+    fn range(&self) -> (f32, f32) {
+        (-1.0, 1.0)
+    }
+}
+
+fn lattice_value(hash: u8) -> f32 {
+    hash as f32 / 127.5 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise::dense_grid;
+
+    #[test]
+    fn same_seed_same_noise() {
+        let (a, b) = (ValueNoise::with_seed(5), ValueNoise::with_seed(5));
+        let other = ValueNoise::with_seed(6);
+        assert!(dense_grid().all(|(x, y)| a.sample2d(x, y) == b.sample2d(x, y)));
+        assert!(dense_grid().any(|(x, y)| a.sample2d(x, y) != other.sample2d(x, y)));
+    }
+
+    #[test]
+    fn stays_in_range() {
+        let noise = ValueNoise::with_seed(5);
+        let (min, max) = noise.range();
+        for (x, y) in dense_grid() {
+            let value = noise.sample2d(x, y);
+            assert!((min..=max).contains(&value), "({x}, {y}) is {value}");
+        }
+    }
+
+    #[test]
+    fn lattice_points_keep_their_values() {
+        let noise = ValueNoise::with_seed(5);
+        for x in -3..3 {
+            for y in -3..3 {
+                let hash = noise.p.hash2(index_wrap(x, 256), index_wrap(y, 256));
+                assert_eq!(noise.sample2d(x as f32, y as f32), lattice_value(hash));
+            }
+        }
+    }
+}
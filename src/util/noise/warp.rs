@@ -0,0 +1,152 @@
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+
+use super::{FractalSettings, NoiseSource};
+
+/// Offsets the sample position of a noise source by two other noise fields.
+///
+/// With more than one iteration the warp is applied recursively, the warp fields
+/// are sampled at the already warped position: `f(p + w(p + w(p)))`.
+#[derive(Clone)]
+pub struct DomainWarp<N, W> {
+    pub source: N,
+    pub warp_x: W,
+    pub warp_y: W,
+    /// offset in sample units for a warp field value of 1.0
+    pub strength: f32,
+    pub iterations: u32,
+}
+
+impl<N: NoiseSource, W: NoiseSource> DomainWarp<N, W> {
+    pub fn new(source: N, warp_x: W, warp_y: W, strength: f32) -> Self {
+        Self {
+            source,
+            warp_x,
+            warp_y,
+            strength,
+            iterations: 1,
+        }
+    }
+
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+}
+
+impl<N: NoiseSource, W: NoiseSource> NoiseSource for DomainWarp<N, W> {
+    fn sample2d(&self, x: f32, y: f32) -> f32 {
+        let (wx, wy) = warp_position(
+            &self.warp_x,
+            &self.warp_y,
+            x,
+            y,
+            self.strength,
+            self.iterations,
+        );
+        self.source.sample2d(wx, wy)
+    }
+
+    fn range(&self) -> (f32, f32) {
+        self.source.range()
+    }
+}
+
+/// the warped sample position of (x, y)
+pub fn warp_position<W: NoiseSource + ?Sized>(
+    warp_x: &W,
+    warp_y: &W,
+    x: f32,
+    y: f32,
+    strength: f32,
+    iterations: u32,
+) -> (f32, f32) {
+    let (mut wx, mut wy) = (x, y);
+    for _ in 0..iterations {
+        let dx = warp_x.sample2d(wx, wy);
+        let dy = warp_y.sample2d(wx, wy);
+        wx = x + strength * dx;
+        wy = y + strength * dy;
+    }
+    (wx, wy)
+}
+
+/// Domain warp used by the terrain generation. The warp fields are fbm perlin noise.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WarpSettings {
+    /// offset in sample units for a warp field value of 1.0
+    pub strength: f32,
+    pub iterations: u32,
+    /// fractal settings of the warp fields
+    pub fractal: FractalSettings,
+}
+
+impl Default for WarpSettings {
+    fn default() -> Self {
+        Self {
+            strength: 20.0,
+            iterations: 1,
+            fractal: FractalSettings {
+                octaves: 4,
+                frequency: 0.01,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise::{dense_grid, Fbm, PerlinNoise};
+
+    fn warp(seed: u64, strength: f32) -> DomainWarp<PerlinNoise, Fbm<PerlinNoise>> {
+        let fractal = FractalSettings {
+            octaves: 3,
+            frequency: 0.2,
+            ..Default::default()
+        };
+        DomainWarp::new(
+            PerlinNoise::with_seed(seed),
+            Fbm::new(PerlinNoise::with_seed(seed + 1), fractal.clone()),
+            Fbm::new(PerlinNoise::with_seed(seed + 2), fractal),
+            strength,
+        )
+    }
+
+    #[test]
+    fn zero_strength_is_the_source() {
+        let warp = warp(5, 0.0).with_iterations(3);
+        for (x, y) in dense_grid() {
+            assert_eq!(warp.sample2d(x, y), warp.source.sample2d(x, y));
+        }
+    }
+
+    #[test]
+    fn same_seed_same_warp() {
+        let (a, b, other) = (warp(5, 4.0), warp(5, 4.0), warp(6, 4.0));
+        assert!(dense_grid().all(|(x, y)| a.sample2d(x, y) == b.sample2d(x, y)));
+        assert!(dense_grid().any(|(x, y)| a.sample2d(x, y) != other.sample2d(x, y)));
+    }
+
+    #[test]
+    fn stays_in_the_source_range() {
+        let warp = warp(5, 4.0).with_iterations(2);
+        let (min, max) = warp.range();
+        for (x, y) in dense_grid() {
+            assert!((min..=max).contains(&warp.sample2d(x, y)));
+        }
+    }
+
+    #[test]
+    fn iterations_warp_the_warped_position() {
+        let warp = warp(5, 4.0).with_iterations(2);
+        let (x, y) = (3.3, -7.1);
+        let offset = |x: f32, y: f32| (warp.warp_x.sample2d(x, y), warp.warp_y.sample2d(x, y));
+        let (dx, dy) = offset(x, y);
+        let (dx, dy) = offset(x + 4.0 * dx, y + 4.0 * dy);
+        let expected = warp.source.sample2d(x + 4.0 * dx, y + 4.0 * dy);
+        assert_eq!(warp.sample2d(x, y), expected);
+    }
+}
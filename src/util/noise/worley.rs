@@ -0,0 +1,158 @@
+use serde::Deserialize;
+
+use super::{periodic, NoiseSource, Period, PermutationTable};
+
+/// Which feature point distances make up the worley noise value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum WorleyMode {
+    /// distance to the closest feature point
+    F1,
+    /// distance to the second closest feature point
+    F2,
+    /// difference of the two, gives cell borders
+    F2MinusF1,
+}
+
+/// Cellular noise with one feature point per lattice cell.
+#[derive(Clone, Deserialize)]
+#[serde(from = "WorleyDescription")]
+pub struct WorleyNoise {
+    p: PermutationTable,
+    pub mode: WorleyMode,
+}
+
+#[derive(Deserialize)]
+struct WorleyDescription {
+    seed: u64,
+    mode: WorleyMode,
+}
+
+impl From<WorleyDescription> for WorleyNoise {
+    fn from(desc: WorleyDescription) -> Self {
+        Self::with_seed(desc.seed, desc.mode)
+    }
+}
+
+impl WorleyNoise {
+    pub fn new(mode: WorleyMode) -> Self {
+        Self {
+            p: PermutationTable::new(),
+            mode,
+        }
+    }
+
+    pub fn with_seed(seed: u64, mode: WorleyMode) -> Self {
+        Self {
+            p: PermutationTable::with_seed(seed),
+            mode,
+        }
+    }
+
+    /// returns the distances (F1, F2) to the two closest feature points
+    pub fn distances(&self, x: f32, y: f32) -> (f32, f32) {
+        self.distances_periodic(x, y, Period::default())
+    }
+
+    /// [`distances`](Self::distances) with feature points that repeat after the given period
+    pub fn distances_periodic(&self, x: f32, y: f32, period: Period) -> (f32, f32) {
+        let cell_x = x.floor() as isize;
+        let cell_y = y.floor() as isize;
+        let mut f1 = f32::MAX;
+        let mut f2 = f32::MAX;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let cx = cell_x + dx;
+                let cy = cell_y + dy;
+                let (px, py) = self.feature_point(cx, cy, period);
+                let d = ((px - x).powi(2) + (py - y).powi(2)).sqrt();
+                if d < f1 {
+                    f2 = f1;
+                    f1 = d;
+                } else if d < f2 {
+                    f2 = d;
+                }
+            }
+        }
+        (f1, f2)
+    }
+
+    pub fn noise2d(&self, x: f32, y: f32) -> f32 {
+        self.noise2d_periodic(x, y, Period::default())
+    }
+
+    pub fn noise2d_periodic(&self, x: f32, y: f32, period: Period) -> f32 {
+        let (f1, f2) = self.distances_periodic(x, y, period);
+        match self.mode {
+            WorleyMode::F1 => f1,
+            WorleyMode::F2 => f2,
+            WorleyMode::F2MinusF1 => f2 - f1,
+        }
+    }
+
+    fn feature_point(&self, cx: isize, cy: isize, period: Period) -> (f32, f32) {
+        let xi = periodic::wrap_cell(cx, period.x);
+        let yi = periodic::wrap_cell(cy, period.y);
+        let h = self.p.hash2(xi, yi) as usize;
+        let ox = self.p.p[h] as f32 / 255.0;
+        let oy = self.p.p[h + 1] as f32 / 255.0;
+        (cx as f32 + ox, cy as f32 + oy)
+    }
+}
+
+impl NoiseSource for WorleyNoise {
+    fn sample2d(&self, x: f32, y: f32) -> f32 {
+        self.noise2d(x, y)
+    }
+
+    fn range(&self) -> (f32, f32) {
+        // the closest point is at most a cell diagonal away, the second closest is
+        // within one of the neighbouring cells
+        match self.mode {
+            WorleyMode::F1 => (0.0, std::f32::consts::SQRT_2),
+            WorleyMode::F2 | WorleyMode::F2MinusF1 => (0.0, 5.0_f32.sqrt()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise::dense_grid;
+
+    const MODES: [WorleyMode; 3] = [WorleyMode::F1, WorleyMode::F2, WorleyMode::F2MinusF1];
+
+    #[test]
+    fn same_seed_same_noise() {
+        let (a, b) = (
+            WorleyNoise::with_seed(5, WorleyMode::F1),
+            WorleyNoise::with_seed(5, WorleyMode::F1),
+        );
+        let other = WorleyNoise::with_seed(6, WorleyMode::F1);
+        assert!(dense_grid().all(|(x, y)| a.distances(x, y) == b.distances(x, y)));
+        assert!(dense_grid().any(|(x, y)| a.distances(x, y) != other.distances(x, y)));
+    }
+
+    #[test]
+    fn f1_is_closer_than_f2() {
+        let noise = WorleyNoise::with_seed(5, WorleyMode::F1);
+        for (x, y) in dense_grid() {
+            let (f1, f2) = noise.distances(x, y);
+            assert!(f1 <= f2, "({x}, {y}) has F1 {f1} and F2 {f2}");
+        }
+    }
+
+    #[test]
+    fn every_mode_stays_in_range() {
+        for mode in MODES {
+            let noise = WorleyNoise::with_seed(5, mode);
+            let (min, max) = noise.range();
+            for (x, y) in dense_grid() {
+                let value = noise.sample2d(x, y);
+                assert!(
+                    (min..=max).contains(&value),
+                    "{mode:?} at ({x}, {y}) is {value}"
+                );
+            }
+        }
+    }
+}